@@ -0,0 +1,95 @@
+// Location and recovery of the app's data files (metadata cache and telemetry databases)
+use rusqlite::{Connection, ErrorCode};
+use std::path::{Path, PathBuf};
+
+/// App folder inside the platform data directory
+pub const APP_DIR_NAME: &str = "com.editorworkshop.app";
+
+/// Environment variable that overrides the data directory (e.g. for portable installs)
+pub(crate) const DATA_DIR_ENV: &str = "EDITORS_WORKSHOP_DATA_DIR";
+
+lazy_static::lazy_static! {
+    // Resolved once on first use
+    static ref DATA_DIR_OVERRIDE: Option<PathBuf> = std::env::var_os(DATA_DIR_ENV)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from);
+}
+
+/// Resolve the directory holding the app's data files
+/// Uses the override if set, otherwise `default_base/com.editorworkshop.app`
+pub fn resolve(default_base: Option<PathBuf>) -> Option<PathBuf> {
+    match DATA_DIR_OVERRIDE.as_ref() {
+        Some(dir) => Some(dir.clone()),
+        None => default_base.map(|base| base.join(APP_DIR_NAME)),
+    }
+}
+
+/// Open a SQLite database, replacing it with a fresh one if the file is corrupt
+/// The corrupt file is kept as `<name>.corrupt.bak`
+pub fn open_database(db_path: &Path) -> Result<Connection, String> {
+    let conn = Connection::open(db_path)
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+
+    // Opening is lazy; the first read is what detects a damaged file
+    match conn.query_row("PRAGMA schema_version", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(conn),
+        Err(e) if is_corruption_error(&e) => {
+            drop(conn);
+            backup_corrupt_database(db_path)?;
+            Connection::open(db_path).map_err(|e| format!("Failed to open database: {}", e))
+        }
+        Err(e) => Err(format!("Failed to open database: {}", e)),
+    }
+}
+
+/// Check whether an error means the database file is corrupt or not a database at all
+fn is_corruption_error(e: &rusqlite::Error) -> bool {
+    matches!(
+        e,
+        rusqlite::Error::SqliteFailure(err, _)
+            if matches!(err.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+    )
+}
+
+/// Move a corrupt database aside so a fresh one can be created in its place
+fn backup_corrupt_database(db_path: &Path) -> Result<(), String> {
+    let backup_path = db_path.with_extension("corrupt.bak");
+    std::fs::rename(db_path, &backup_path)
+        .map_err(|e| format!("Failed to back up corrupt database: {}", e))?;
+
+    // WAL and shared-memory files belong to the corrupt database
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = db_path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let _ = std::fs::remove_file(sidecar);
+    }
+
+    eprintln!(
+        "Database was corrupt, moved to {} and recreated",
+        backup_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn corrupt_database_is_backed_up_and_recreated() {
+        let dir = test_support::temp_dir("corrupt_db");
+        let db_path = dir.join("smelter_cache.db");
+        let garbage = vec![0xab; 4096];
+        std::fs::write(&db_path, &garbage).unwrap();
+
+        let conn = open_database(&db_path).unwrap();
+
+        conn.execute("CREATE TABLE usable (id INTEGER)", [])
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("smelter_cache.corrupt.bak")).unwrap(),
+            garbage
+        );
+    }
+}
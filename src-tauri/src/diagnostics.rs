@@ -0,0 +1,94 @@
+// One-shot health check of the app's data files, for support requests
+use serde::Serialize;
+use std::path::Path;
+
+use crate::data_dir;
+use crate::smelter;
+use crate::telemetry;
+
+/// Result of the self-check; each `*_error` is None when that check passed
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsReport {
+    /// Folder holding the metadata cache
+    pub data_dir: String,
+    pub data_dir_exists: bool,
+    pub data_dir_error: Option<String>,
+    pub cache_db_path: String,
+    pub cache_db_error: Option<String>,
+    /// None if the platform has no data directory for telemetry
+    pub telemetry_db_path: Option<String>,
+    pub telemetry_db_error: Option<String>,
+    pub sqlite_version: String,
+    /// Free space on the data directory's drive (None if it couldn't be measured)
+    pub available_bytes: Option<u64>,
+}
+
+/// Check that the data directory is writable and both databases open
+pub fn run() -> DiagnosticsReport {
+    let cache_db_path = smelter::cache::get_db_path();
+    let data_dir = cache_db_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let telemetry_db_path = telemetry::get_db_path();
+
+    DiagnosticsReport {
+        data_dir: data_dir.to_string_lossy().to_string(),
+        data_dir_exists: data_dir.is_dir(),
+        data_dir_error: check_writable(&data_dir).err(),
+        cache_db_path: cache_db_path.to_string_lossy().to_string(),
+        cache_db_error: check_database(&cache_db_path).err(),
+        telemetry_db_path: telemetry_db_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string()),
+        telemetry_db_error: match &telemetry_db_path {
+            Some(path) => check_database(path).err(),
+            None => Some("Could not find data directory".to_string()),
+        },
+        sqlite_version: rusqlite::version().to_string(),
+        available_bytes: fs2::available_space(&data_dir).ok(),
+    }
+}
+
+/// Create and remove a scratch file to prove the folder accepts writes
+fn check_writable(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(".write_test");
+    std::fs::write(&probe, b"ok")
+        .map_err(|e| format!("Cannot write to '{}': {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Open a database and run a trivial query
+fn check_database(db_path: &Path) -> Result<(), String> {
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let conn = data_dir::open_database(db_path)?;
+    conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+        .map(|_| ())
+        .map_err(|e| format!("Database query failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn report_is_populated_for_a_writable_data_dir() {
+        let _databases = test_support::use_test_databases();
+        let data = std::path::PathBuf::from(std::env::var_os(data_dir::DATA_DIR_ENV).unwrap());
+
+        let report = run();
+
+        assert!(Path::new(&report.data_dir).starts_with(&data));
+        assert!(report.data_dir_exists);
+        assert_eq!(report.data_dir_error, None);
+        assert_eq!(report.cache_db_error, None);
+        assert!(Path::new(&report.telemetry_db_path.unwrap()).starts_with(&data));
+        assert_eq!(report.telemetry_db_error, None);
+        assert!(!report.sqlite_version.is_empty());
+        assert!(report.available_bytes.is_some_and(|bytes| bytes > 0));
+    }
+}
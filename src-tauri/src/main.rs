@@ -1,123 +1,434 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod data_dir;
+mod diagnostics;
 mod smelter;
 mod telemetry;
+#[cfg(test)]
+mod test_support;
 
-use smelter::{AudioMetadata, DuplicateInfo, OrganizeResult, SourceDuplicateGroup};
+use smelter::{
+    AudioMetadata, CacheFreshness, CacheStats, ContentDuplicateGroup, DuplicateInfo,
+    IncrementalScanReport, MoodCommentOptions, OrganizeOptions, OrganizeResult, PreviewEntry,
+    ScanOptions, ScanReport, SmelterError, SourceDuplicateGroup, SpaceCheck,
+};
 use std::collections::HashMap;
+use tauri::Emitter;
 
 // ============ The Smelter Commands ============
 
+/// Number of freshly read files to write to the cache per transaction
+const CACHE_BATCH_SIZE: usize = 500;
+
+/// Write pending metadata to the cache in one transaction (storing content hashes if requested)
+fn flush_cache_batch(pending: &mut Vec<AudioMetadata>, store_hashes: bool) {
+    let _ = smelter::cache::cache_metadata_batch(pending);
+    if store_hashes {
+        for metadata in pending.iter() {
+            let _ = smelter::cache::get_content_hash(&metadata.path);
+        }
+    }
+    pending.clear();
+}
+
 /// Scan audio files for metadata (ID3 tags)
+/// Cached files are served from the cache; the rest are read in parallel. Results keep the
+/// order of `paths`
+/// With `validate_by_hash`, touched-but-unchanged files are still served from the cache
+/// With `include_cover_art`, embedded art is returned as base64 (slow, off by default)
+/// Pass an `operation_id` to make the scan cancellable via `cancel_operation`
+/// (files not read yet when cancelled are left out)
+/// `is_sfx` is set using `sfx_prefix` (pass the organize options' prefix; defaults to "ES_")
 #[tauri::command]
-async fn scan_audio_files(paths: Vec<String>) -> Result<Vec<AudioMetadata>, String> {
+async fn scan_audio_files(
+    paths: Vec<String>,
+    validate_by_hash: Option<bool>,
+    include_cover_art: Option<bool>,
+    operation_id: Option<String>,
+    sfx_prefix: Option<String>,
+) -> Result<Vec<AudioMetadata>, SmelterError> {
+    let validate_by_hash = validate_by_hash.unwrap_or(false);
+    let include_cover_art = include_cover_art.unwrap_or(false);
+
     // Initialize database on first scan
     smelter::cache::init_database()?;
 
-    let mut results = Vec::new();
+    // Check the cache first (one slot per input path, None = needs reading)
+    let mut slots: Vec<Option<AudioMetadata>> = paths
+        .iter()
+        .map(|path| {
+            smelter::cache::get_cached_metadata(path, validate_by_hash)
+                .ok()
+                .flatten()
+        })
+        .collect();
+    let (missing_indices, missing_paths): (Vec<usize>, Vec<String>) = slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.is_none())
+        .map(|(index, _)| (index, paths[index].clone()))
+        .unzip();
 
-    for path in paths {
-        // Check cache first
-        if let Ok(Some(cached)) = smelter::cache::get_cached_metadata(&path) {
-            results.push(cached);
-            continue;
-        }
+    // Read the rest in parallel
+    let cancel = operation_id
+        .as_deref()
+        .map(smelter::cancel::register)
+        .unwrap_or_default();
+    let read = smelter::metadata::read_files_ordered(&missing_paths, &cancel);
+    if let Some(id) = &operation_id {
+        smelter::cancel::unregister(id);
+    }
 
-        // Read metadata from file
-        match smelter::metadata::read_audio_metadata_full(&path) {
-            Ok(metadata) => {
-                // Cache the result
-                let _ = smelter::cache::cache_metadata(&metadata);
-                results.push(metadata);
+    // Freshly read metadata waiting to be cached
+    let mut pending_cache = Vec::new();
+
+    for (index, result) in missing_indices.into_iter().zip(read) {
+        let path = &paths[index];
+        match result {
+            Some(Ok(metadata)) => {
+                // Cache the result (in batches)
+                pending_cache.push(metadata.clone());
+                if pending_cache.len() >= CACHE_BATCH_SIZE {
+                    flush_cache_batch(&mut pending_cache, validate_by_hash);
+                }
+                slots[index] = Some(metadata);
             }
-            Err(e) => {
+            Some(Err(e)) => {
                 eprintln!("Error scanning {}: {}", path, e);
                 // Return partial result with error info
-                results.push(AudioMetadata {
+                slots[index] = Some(AudioMetadata {
                     path: path.clone(),
-                    filename: std::path::Path::new(&path)
+                    filename: std::path::Path::new(path)
                         .file_name()
                         .and_then(|n| n.to_str())
                         .unwrap_or("Unknown")
                         .to_string(),
-                    title: None,
-                    artist: None,
-                    genre: None,
-                    mood: None,
-                    energy: None,
-                    bpm: None,
-                    duration_secs: None,
-                    category_override: None,
+                    ..Default::default()
                 });
             }
+            // Skipped by cancellation, so the slot stays empty
+            None => {}
+        }
+    }
+
+    flush_cache_batch(&mut pending_cache, validate_by_hash);
+
+    let mut results: Vec<AudioMetadata> = slots.into_iter().flatten().collect();
+    smelter::organize::mark_sfx(&mut results, &sfx_prefix_or_default(sfx_prefix));
+
+    // Cover art is never cached, so read it separately
+    if include_cover_art {
+        for metadata in results.iter_mut() {
+            metadata.cover_art = smelter::metadata::read_cover_art(&metadata.path)
+                .ok()
+                .flatten();
         }
     }
 
     Ok(results)
 }
 
+/// Read fresh metadata for a single file (bypasses the cache)
+#[tauri::command]
+async fn read_metadata(
+    path: String,
+    sfx_prefix: Option<String>,
+) -> Result<AudioMetadata, SmelterError> {
+    let mut metadata = smelter::metadata::read_audio_metadata_full(&path)?;
+    smelter::organize::mark_sfx([&mut metadata], &sfx_prefix_or_default(sfx_prefix));
+    Ok(metadata)
+}
+
+/// The music prefix a scan marks SFX files with: the caller's, or organizing's default
+fn sfx_prefix_or_default(sfx_prefix: Option<String>) -> String {
+    sfx_prefix.unwrap_or_else(|| smelter::organize::DEFAULT_SFX_PREFIX.to_string())
+}
+
+/// Get a file's embedded cover art as base64 (None if the file has no art)
+#[tauri::command]
+async fn get_cover_art(path: String) -> Result<Option<String>, SmelterError> {
+    smelter::metadata::read_cover_art(&path)
+}
+
 /// Scan a directory recursively for audio files
+/// Pass an `operation_id` to make the scan cancellable via `cancel_operation`
+/// `options` can restrict the scan with include/exclude filename globs
+/// Files that couldn't be read are listed in the report's `failures`
+/// `is_sfx` is set using `sfx_prefix`, as in `scan_audio_files`
+#[tauri::command]
+async fn scan_directory(
+    path: String,
+    operation_id: Option<String>,
+    options: Option<ScanOptions>,
+    sfx_prefix: Option<String>,
+) -> Result<ScanReport, SmelterError> {
+    smelter::cache::init_database()?;
+    let options = options.unwrap_or_default();
+    let cancel = operation_id
+        .as_deref()
+        .map(smelter::cancel::register)
+        .unwrap_or_default();
+    let result = smelter::metadata::scan_directory(&path, &options, &cancel);
+    if let Some(id) = &operation_id {
+        smelter::cancel::unregister(id);
+    }
+    let mut report = result?;
+    smelter::organize::mark_sfx(&mut report.files, &sfx_prefix_or_default(sfx_prefix));
+    Ok(report)
+}
+
+/// File extensions recognized as audio (lowercase, without the dot), e.g. for file dialogs
+#[tauri::command]
+async fn get_audio_extensions() -> Vec<String> {
+    let mut extensions: Vec<String> = smelter::metadata::audio_extensions().into_iter().collect();
+    extensions.sort();
+    extensions
+}
+
+/// Watch a folder for audio file changes
+/// Emits `smelter://file-added` with metadata for created or modified files
+/// and `smelter://file-removed` with the path of deleted files
 #[tauri::command]
-async fn scan_directory(path: String) -> Result<Vec<AudioMetadata>, String> {
+async fn watch_directory(app: tauri::AppHandle, path: String) -> Result<(), SmelterError> {
     smelter::cache::init_database()?;
-    smelter::metadata::scan_directory(&path)
+    smelter::watch::watch_directory(&path, move |event| {
+        let _ = match event {
+            smelter::watch::WatchEvent::Added(metadata) => app.emit("smelter://file-added", metadata),
+            smelter::watch::WatchEvent::Removed(path) => app.emit("smelter://file-removed", path),
+        };
+    })
+}
+
+/// Stop watching a folder
+/// Returns false if the folder wasn't being watched
+#[tauri::command]
+async fn stop_watching(path: String) -> bool {
+    smelter::watch::stop_watching(&path)
+}
+
+/// Re-scan a directory, only re-reading files that changed since the last scan
+/// Takes the same `operation_id`, `options` and `sfx_prefix` as `scan_directory`
+#[tauri::command]
+async fn scan_directory_incremental(
+    path: String,
+    operation_id: Option<String>,
+    options: Option<ScanOptions>,
+    sfx_prefix: Option<String>,
+) -> Result<IncrementalScanReport, SmelterError> {
+    smelter::cache::init_database()?;
+    let options = options.unwrap_or_default();
+    let cancel = operation_id
+        .as_deref()
+        .map(smelter::cancel::register)
+        .unwrap_or_default();
+    let result = smelter::metadata::scan_directory_incremental(&path, &options, &cancel);
+    if let Some(id) = &operation_id {
+        smelter::cancel::unregister(id);
+    }
+    let mut report = result?;
+    smelter::organize::mark_sfx(&mut report.changed, &sfx_prefix_or_default(sfx_prefix));
+    Ok(report)
 }
 
 /// Preview organization without moving files
+/// With an `output_folder`, each entry also carries its final (collision-resolved) filename
 #[tauri::command]
 async fn preview_organization(
     files: Vec<AudioMetadata>,
-    organize_by: String,
-) -> Result<HashMap<String, Vec<String>>, String> {
-    Ok(smelter::organize::preview_organization(&files, &organize_by))
+    options: OrganizeOptions,
+    output_folder: Option<String>,
+) -> Result<HashMap<String, Vec<PreviewEntry>>, SmelterError> {
+    Ok(match output_folder {
+        Some(output_folder) => {
+            smelter::organize::preview_final_names(&files, &output_folder, &options)
+        }
+        None => smelter::organize::preview_organization(&files, &options),
+    })
 }
 
 /// Organize files into folders
+/// Emits `organize://progress` events with `{done, total, current_filename}`
+/// Pass an `operation_id` to make the run cancellable via `cancel_operation`
 #[tauri::command]
 async fn organize_files(
+    app: tauri::AppHandle,
     files: Vec<AudioMetadata>,
     output_folder: String,
-    organize_by: String,
-    operation: String,
-) -> Result<OrganizeResult, String> {
-    smelter::organize::organize_files(&files, &output_folder, &organize_by, &operation)
+    options: OrganizeOptions,
+    operation_id: Option<String>,
+) -> Result<OrganizeResult, SmelterError> {
+    let cancel = operation_id
+        .as_deref()
+        .map(smelter::cancel::register)
+        .unwrap_or_default();
+    let result = smelter::organize::organize_files(&files, &output_folder, &options, &cancel, |progress| {
+        let _ = app.emit("organize://progress", progress);
+    });
+    if let Some(id) = &operation_id {
+        smelter::cancel::unregister(id);
+    }
+
+    // Remember what was placed so the run can be undone
+    if let Ok(ref organized) = result {
+        if let Err(e) = smelter::undo::record_organize(&options.operation, &organized.moved) {
+            eprintln!("{}", e);
+        }
+    }
+    result
+}
+
+/// Undo the last organize run (moves files back, or deletes copies and links)
+#[tauri::command]
+async fn undo_last_organize() -> Result<OrganizeResult, SmelterError> {
+    smelter::undo::undo_last_organize()
+}
+
+/// Check that the output folder has enough free space for organizing the files
+#[tauri::command]
+async fn check_space(
+    files: Vec<AudioMetadata>,
+    output_folder: String,
+    options: OrganizeOptions,
+) -> Result<SpaceCheck, SmelterError> {
+    smelter::organize::check_space(&files, &output_folder, &options)
+}
+
+/// Cancel a running scan, organize or duplicate check by id
+/// Returns false if the operation already finished
+#[tauri::command]
+async fn cancel_operation(id: String) -> bool {
+    smelter::cancel::cancel(&id)
 }
 
 /// Clear the metadata cache
 #[tauri::command]
-async fn clear_metadata_cache() -> Result<u32, String> {
+async fn clear_metadata_cache() -> Result<u32, SmelterError> {
     smelter::cache::clear_cache()
 }
 
+/// Shrink the metadata cache file, returning the number of bytes reclaimed
+#[tauri::command]
+async fn vacuum_cache() -> Result<u64, SmelterError> {
+    smelter::cache::vacuum_cache()
+}
+
+/// Get metadata cache statistics
+#[tauri::command]
+async fn get_cache_stats() -> Result<CacheStats, SmelterError> {
+    smelter::cache::get_cache_stats()
+}
+
+/// Report for each file whether its cached metadata is Fresh, Stale or Missing (file gone)
+/// Only compares mtime and size, so it's cheap enough to badge files needing a rescan
+#[tauri::command]
+async fn check_cache_freshness(paths: Vec<String>) -> Result<Vec<CacheFreshness>, SmelterError> {
+    smelter::cache::check_freshness(&paths)
+}
+
+/// Set the maximum number of cached metadata entries (0 = unlimited)
+/// Returns how many entries were evicted to fit the new limit
+#[tauri::command]
+async fn set_cache_limit(limit: u32) -> Result<u32, SmelterError> {
+    smelter::cache::set_cache_limit(limit)
+}
+
+/// Set how much memory (in MB) duplicate detection may use for hashing files in parallel
+/// 0 restores the default; returns how many files are hashed at once
+#[tauri::command]
+async fn set_hash_memory_budget(megabytes: u32) -> u32 {
+    smelter::hash::set_hash_memory_budget(megabytes)
+}
+
+/// Save the metadata cache to a JSON file (e.g. to carry it to another machine)
+/// Returns how many entries were exported
+#[tauri::command]
+async fn export_cache(path: String) -> Result<u32, SmelterError> {
+    smelter::cache::export_cache(&path)
+}
+
+/// Load a JSON file written by `export_cache` into the metadata cache
+/// Returns how many entries were imported
+#[tauri::command]
+async fn import_cache(path: String) -> Result<u32, SmelterError> {
+    smelter::cache::import_cache(&path)
+}
+
+/// Set which file extensions are scanned as audio (e.g. ["mp3", ".AIFF"])
+/// An empty list restores the defaults; returns how many extensions are recognized
+#[tauri::command]
+async fn set_audio_extensions(extensions: Vec<String>) -> u32 {
+    smelter::metadata::set_audio_extensions(&extensions)
+}
+
+/// Set the rules for reading a comment as a mood (length limit, rejected characters, keywords)
+#[tauri::command]
+async fn set_mood_comment_options(options: MoodCommentOptions) {
+    smelter::metadata::set_mood_comment_options(options)
+}
+
+/// Load a JSON genre map (`{"Indie Pop": "Pop"}`) used when organizing by genre
+/// Returns how many genres are mapped
+#[tauri::command]
+async fn load_genre_map(path: String) -> Result<u32, SmelterError> {
+    smelter::genre_map::load_genre_map(&path)
+}
+
 /// Find duplicate files that already exist in target folders
+/// Emits `duplicates://progress` events with `{done, total, current_filename}`
+/// Pass an `operation_id` to make the check cancellable via `cancel_operation`
+/// (a cancelled check returns the duplicates found so far)
 #[tauri::command]
 async fn find_duplicates(
+    app: tauri::AppHandle,
     files: Vec<AudioMetadata>,
     output_folder: String,
-    organize_by: String,
-) -> Result<Vec<DuplicateInfo>, String> {
-    Ok(smelter::organize::find_duplicates(&files, &output_folder, &organize_by))
+    options: OrganizeOptions,
+    operation_id: Option<String>,
+) -> Result<Vec<DuplicateInfo>, SmelterError> {
+    let cancel = operation_id
+        .as_deref()
+        .map(smelter::cancel::register)
+        .unwrap_or_default();
+    let duplicates =
+        smelter::organize::find_duplicates(&files, &output_folder, &options, &cancel, |progress| {
+            let _ = app.emit("duplicates://progress", progress);
+        });
+    if let Some(id) = &operation_id {
+        smelter::cancel::unregister(id);
+    }
+    Ok(duplicates)
 }
 
-/// Delete duplicate files
+/// Delete duplicate files (moved to the trash unless `permanent` is set)
 #[tauri::command]
-async fn delete_duplicates(paths: Vec<String>) -> Result<(u32, Vec<String>), String> {
-    smelter::organize::delete_duplicates(&paths)
+async fn delete_duplicates(
+    paths: Vec<String>,
+    permanent: Option<bool>,
+) -> Result<(u32, Vec<String>), SmelterError> {
+    smelter::organize::delete_duplicates(&paths, permanent.unwrap_or(false))
 }
 
 /// Find source files with same filename going to same category (before organizing)
+/// With `fuzzy`, names differing only in case, spacing or separators are grouped as well
 #[tauri::command]
 async fn find_source_duplicates(
     files: Vec<AudioMetadata>,
-    organize_by: String,
+    options: OrganizeOptions,
+    fuzzy: Option<bool>,
 ) -> Vec<SourceDuplicateGroup> {
-    smelter::organize::find_source_duplicates(&files, &organize_by)
+    smelter::organize::find_source_duplicates(&files, &options, fuzzy.unwrap_or(false))
+}
+
+/// Find files with identical contents anywhere in the given files (library-wide cleanup)
+#[tauri::command]
+async fn find_all_duplicates(files: Vec<AudioMetadata>) -> Vec<ContentDuplicateGroup> {
+    smelter::organize::find_all_duplicates(&files)
 }
 
 /// Rescan files - clears cache for specified files and re-reads metadata
 #[tauri::command]
-async fn rescan_files(paths: Vec<String>) -> Result<Vec<AudioMetadata>, String> {
+async fn rescan_files(paths: Vec<String>) -> Result<Vec<AudioMetadata>, SmelterError> {
     // Clear cache for these files
     smelter::cache::clear_cache_for_files(&paths)?;
 
@@ -140,14 +451,7 @@ async fn rescan_files(paths: Vec<String>) -> Result<Vec<AudioMetadata>, String>
                         .and_then(|n| n.to_str())
                         .unwrap_or("Unknown")
                         .to_string(),
-                    title: None,
-                    artist: None,
-                    genre: None,
-                    mood: None,
-                    energy: None,
-                    bpm: None,
-                    duration_secs: None,
-                    category_override: None,
+                    ..Default::default()
                 });
             }
         }
@@ -156,29 +460,161 @@ async fn rescan_files(paths: Vec<String>) -> Result<Vec<AudioMetadata>, String>
     Ok(results)
 }
 
+/// Write the same tag value (field: "genre", "mood" or "energy") to many files
+/// A failure on one file doesn't stop the others; the cache is refreshed for every written file
+#[tauri::command]
+async fn set_field_bulk(
+    paths: Vec<String>,
+    field: String,
+    value: String,
+) -> Result<OrganizeResult, SmelterError> {
+    if !matches!(field.as_str(), "genre" | "mood" | "energy") {
+        return Err(SmelterError::InvalidInput(format!("Unsupported field: {}", field)));
+    }
+
+    let mut result = OrganizeResult {
+        success_count: 0,
+        error_count: 0,
+        skipped_count: 0,
+        errors: Vec::new(),
+        cancelled: false,
+        destination_unavailable: false,
+        planned: Vec::new(),
+        moved: Vec::new(),
+    };
+
+    for path in &paths {
+        match smelter::metadata::write_audio_metadata(path, &field, &value) {
+            Ok(_) => {
+                result.success_count += 1;
+                // Refresh the cache with the new tags
+                if let Ok(metadata) = smelter::metadata::read_audio_metadata_full(path) {
+                    let _ = smelter::cache::cache_metadata(&metadata);
+                }
+            }
+            Err(e) => {
+                result.error_count += 1;
+                result.errors.push(format!("{}: {}", path, e));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Show a file in Finder/Explorer with it selected (Linux opens the containing folder)
+#[tauri::command]
+async fn reveal_in_file_manager(path: String) -> Result<(), SmelterError> {
+    reveal_command(std::path::Path::new(&path))?
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| SmelterError::Io(format!("Failed to open file manager: {}", e)))
+}
+
+/// Build the platform's command for revealing a file
+fn reveal_command(path: &std::path::Path) -> Result<std::process::Command, SmelterError> {
+    if !path.exists() {
+        return Err(SmelterError::NotFound(format!(
+            "File not found: '{}' may have been moved or deleted.",
+            path.display()
+        )));
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let mut command = std::process::Command::new("explorer");
+        command.arg(format!("/select,{}", path.display()));
+        Ok(command)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let mut command = std::process::Command::new("open");
+        command.arg("-R").arg(path);
+        Ok(command)
+    }
+    // xdg-open can't select a file, so open its folder instead
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        let mut command = std::process::Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(path));
+        Ok(command)
+    }
+    #[cfg(not(any(unix, target_os = "windows")))]
+    {
+        Err(SmelterError::Other(
+            "Revealing files isn't supported on this platform".to_string(),
+        ))
+    }
+}
+
 // ============ Telemetry Commands ============
 
-/// Queue a telemetry event for later sending
+/// Queue a telemetry event for later sending (higher `priority` is sent first)
+/// With `dedup_window_secs`, repeats of an identical unsent event within that window are
+/// counted on the earlier event instead of queued again
 #[tauri::command]
 async fn queue_telemetry_event(
     event_type: String,
     payload: serde_json::Value,
-) -> Result<(), String> {
-    telemetry::queue_event(&telemetry::QueuedEvent { event_type, payload })
+    priority: Option<i32>,
+    dedup_window_secs: Option<u32>,
+) -> Result<(), SmelterError> {
+    telemetry::queue_event(
+        &telemetry::QueuedEvent {
+            event_type,
+            payload,
+            attempts: 0,
+            priority: priority.unwrap_or(0),
+            count: 1,
+        },
+        dedup_window_secs.unwrap_or(0),
+    )
 }
 
-/// Get pending telemetry events
+/// Get a page of pending telemetry events (`limit` defaults to 100, capped at 1000)
 #[tauri::command]
-async fn get_pending_telemetry() -> Result<Vec<(i64, telemetry::QueuedEvent)>, String> {
-    telemetry::get_pending_events()
+async fn get_pending_telemetry(
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Vec<(i64, telemetry::QueuedEvent)>, SmelterError> {
+    telemetry::get_pending_events(
+        limit.unwrap_or(telemetry::DEFAULT_PENDING_LIMIT),
+        offset.unwrap_or(0),
+    )
+}
+
+/// Number of telemetry events waiting to be sent
+#[tauri::command]
+async fn count_pending_telemetry() -> Result<u32, SmelterError> {
+    telemetry::count_pending_events()
 }
 
 /// Mark telemetry events as sent
 #[tauri::command]
-async fn mark_telemetry_sent(ids: Vec<i64>) -> Result<(), String> {
+async fn mark_telemetry_sent(ids: Vec<i64>) -> Result<(), SmelterError> {
     telemetry::mark_sent(&ids)
 }
 
+/// Enable or disable telemetry (disabled drops events instead of queueing them)
+#[tauri::command]
+async fn set_telemetry_enabled(enabled: bool) -> Result<(), SmelterError> {
+    telemetry::set_enabled(enabled)
+}
+
+/// Record failed attempts to send telemetry events
+#[tauri::command]
+async fn record_telemetry_failure(ids: Vec<i64>) -> Result<(), SmelterError> {
+    telemetry::record_send_failure(&ids)
+}
+
+// ============ Diagnostics Commands ============
+
+/// Check the data directory and databases (writable, opening, SQLite version, free space)
+#[tauri::command]
+async fn diagnostics() -> diagnostics::DiagnosticsReport {
+    diagnostics::run()
+}
+
 fn main() {
     // Initialize database (migrations handle one-time cache clears)
     let _ = smelter::cache::init_database();
@@ -224,17 +660,124 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             scan_audio_files,
             scan_directory,
+            scan_directory_incremental,
+            get_audio_extensions,
+            read_metadata,
+            get_cover_art,
+            watch_directory,
+            stop_watching,
             preview_organization,
             organize_files,
+            undo_last_organize,
+            check_space,
             clear_metadata_cache,
+            set_cache_limit,
+            set_hash_memory_budget,
+            export_cache,
+            import_cache,
+            set_audio_extensions,
+            set_mood_comment_options,
+            get_cache_stats,
+            check_cache_freshness,
+            vacuum_cache,
+            load_genre_map,
             find_duplicates,
             delete_duplicates,
             find_source_duplicates,
+            find_all_duplicates,
             rescan_files,
+            set_field_bulk,
+            reveal_in_file_manager,
+            cancel_operation,
             queue_telemetry_event,
             get_pending_telemetry,
+            count_pending_telemetry,
             mark_telemetry_sent,
+            record_telemetry_failure,
+            set_telemetry_enabled,
+            diagnostics,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_metadata_returns_the_file_tags() {
+        let dir = test_support::temp_dir("read_metadata_command");
+        let path = dir.join("ES_Anthem.flac");
+        test_support::write_flac(
+            &path,
+            &[
+                ("TITLE", "Anthem"),
+                ("GENRE", "Rock"),
+                ("MOOD", "Energetic"),
+            ],
+        );
+
+        let metadata =
+            tauri::async_runtime::block_on(read_metadata(path.to_string_lossy().to_string()))
+                .unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Anthem"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.mood.as_deref(), Some("Energetic"));
+    }
+
+    #[test]
+    fn reveal_rejects_a_missing_path() {
+        let dir = test_support::temp_dir("reveal_missing");
+
+        let err = reveal_command(&dir.join("gone.wav")).unwrap_err();
+
+        assert_eq!(err.kind(), "NotFound");
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn reveal_opens_the_containing_folder_on_linux() {
+        let dir = test_support::temp_dir("reveal_linux");
+        let file = test_support::write_file(&dir, "ES_Kick.wav", b"kick");
+
+        let command = reveal_command(std::path::Path::new(&file)).unwrap();
+
+        assert_eq!(command.get_program(), "xdg-open");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec![dir.as_os_str()]
+        );
+    }
+
+    #[test]
+    fn set_field_bulk_writes_every_file_despite_failures() {
+        let _databases = test_support::use_test_databases();
+        smelter::cache::init_database().unwrap();
+        let dir = test_support::temp_dir("set_field_bulk");
+        let mut paths: Vec<String> = ["ES_One.flac", "ES_Two.flac", "ES_Three.flac"]
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                test_support::write_flac(&path, &[("TITLE", "Untitled"), ("GENRE", "Rock")]);
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        paths.insert(1, dir.join("missing.flac").to_string_lossy().to_string());
+
+        let result = tauri::async_runtime::block_on(set_field_bulk(
+            paths.clone(),
+            "genre".to_string(),
+            "Ambient".to_string(),
+        ))
+        .unwrap();
+
+        assert_eq!(result.success_count, 3);
+        assert_eq!(result.error_count, 1);
+        assert!(result.errors[0].contains("missing.flac"));
+        for path in paths.iter().filter(|path| !path.contains("missing")) {
+            let metadata = smelter::metadata::read_audio_metadata_full(path).unwrap();
+            assert_eq!(metadata.genre.as_deref(), Some("Ambient"), "{}", path);
+        }
+    }
+}
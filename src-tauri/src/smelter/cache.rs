@@ -1,15 +1,24 @@
 // SQLite caching for audio metadata
+use lru::LruCache;
 use rusqlite::{Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::AudioMetadata;
+use crate::data_dir;
 
-/// Run one-time migration to clear stale cache data
-fn run_cache_clear_migration(conn: &Connection) -> Result<(), String> {
-    let migration_name = "clear_stale_cache_v2";
+use super::fingerprint;
+use super::hash::hash_file;
+use super::metadata::{format_duration, parse_energy_level};
+use super::{AudioMetadata, CacheFreshness, CacheStats, FreshnessStatus, SmelterError};
 
+/// Run a one-time migration to clear stale cache data (e.g. after a new column is added)
+fn run_cache_clear_migration(conn: &Connection, migration_name: &str) -> Result<(), SmelterError> {
     // Check if migration already ran
     let already_ran: bool = conn
         .query_row(
@@ -25,7 +34,7 @@ fn run_cache_clear_migration(conn: &Connection) -> Result<(), String> {
 
     // Clear all cached metadata to force fresh reads
     conn.execute("DELETE FROM audio_metadata", [])
-        .map_err(|e| format!("Failed to clear cache in migration: {}", e))?;
+        .map_err(|e| SmelterError::Cache(format!("Failed to clear cache in migration: {}", e)))?;
 
     // Mark migration as complete
     let now = std::time::SystemTime::now()
@@ -37,18 +46,17 @@ fn run_cache_clear_migration(conn: &Connection) -> Result<(), String> {
         "INSERT INTO migrations (name, applied_at) VALUES (?1, ?2)",
         rusqlite::params![migration_name, now],
     )
-    .map_err(|e| format!("Failed to record migration: {}", e))?;
+    .map_err(|e| SmelterError::Cache(format!("Failed to record migration: {}", e)))?;
 
     eprintln!("Cache cleared for improved metadata reading");
     Ok(())
 }
 
 /// Get the database path in the app data directory
-fn get_db_path() -> PathBuf {
-    // Use a standard location for the database
-    let mut path = dirs_next::data_local_dir()
-        .unwrap_or_else(|| PathBuf::from("."));
-    path.push("com.editorworkshop.app");
+pub fn get_db_path() -> PathBuf {
+    // Use a standard location for the database (unless overridden)
+    let mut path = data_dir::resolve(dirs_next::data_local_dir())
+        .unwrap_or_else(|| PathBuf::from(".").join(data_dir::APP_DIR_NAME));
     std::fs::create_dir_all(&path).ok();
     path.push("smelter_cache.db");
     path
@@ -59,11 +67,64 @@ lazy_static::lazy_static! {
     static ref DB: Mutex<Option<Connection>> = Mutex::new(None);
 }
 
+/// Most recently used entries kept in memory in front of the database
+const MEMORY_CACHE_CAPACITY: usize = 5_000;
+
+/// A metadata lookup remembered in memory, valid while the file's mtime and size are unchanged
+struct MemoryEntry {
+    file_modified: i64,
+    file_size: i64,
+    metadata: AudioMetadata,
+}
+
+// Recently read entries, so repeated scans of the same files skip the database
+lazy_static::lazy_static! {
+    static ref MEMORY_CACHE: Mutex<LruCache<String, MemoryEntry>> = Mutex::new(LruCache::new(
+        NonZeroUsize::new(MEMORY_CACHE_CAPACITY).expect("capacity is non-zero")
+    ));
+}
+
+/// Lock the in-memory layer, recovering it if a thread panicked while holding the lock
+fn lock_memory() -> MutexGuard<'static, LruCache<String, MemoryEntry>> {
+    MEMORY_CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Drop in-memory entries for files whose database rows changed
+fn forget_in_memory<'a>(paths: impl IntoIterator<Item = &'a str>) {
+    let mut memory = lock_memory();
+    for path in paths {
+        memory.pop(path);
+    }
+}
+
+/// Lock the database connection, recovering the guard if a thread panicked while holding it
+/// (the connection stays usable, so one panic mustn't break the cache for the whole session)
+fn lock_db() -> MutexGuard<'static, Option<Connection>> {
+    DB.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Default maximum number of cached entries
+const DEFAULT_CACHE_LIMIT: u32 = 50_000;
+
+/// Maximum number of cached entries (0 = unlimited)
+static CACHE_LIMIT: AtomicU32 = AtomicU32::new(DEFAULT_CACHE_LIMIT);
+
 /// Initialize the database and create tables
-pub fn init_database() -> Result<(), String> {
+pub fn init_database() -> Result<(), SmelterError> {
+    // Hold the lock until the connection is stored so concurrent callers can't both open it
+    let mut db = lock_db();
+    if db.is_some() {
+        return Ok(());
+    }
+
     let db_path = get_db_path();
-    let conn = Connection::open(&db_path)
-        .map_err(|e| format!("Failed to open database: {}", e))?;
+    let conn = data_dir::open_database(&db_path).map_err(SmelterError::Cache)?;
+
+    // WAL allows reads while a write is in progress; NORMAL sync is safe with WAL
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+        .map_err(|e| SmelterError::Cache(format!("Failed to enable WAL mode: {}", e)))?;
+    conn.pragma_update(None, "synchronous", "NORMAL")
+        .map_err(|e| SmelterError::Cache(format!("Failed to set synchronous mode: {}", e)))?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS audio_metadata (
@@ -78,16 +139,58 @@ pub fn init_database() -> Result<(), String> {
             energy TEXT,
             bpm INTEGER,
             duration_secs REAL,
+            year INTEGER,
+            album TEXT,
+            track_number INTEGER,
+            content_hash TEXT,
+            sample_rate INTEGER,
+            bit_depth INTEGER,
+            channels INTEGER,
+            bitrate_kbps INTEGER,
+            fingerprint BLOB,
+            genres TEXT,
+            custom_fields TEXT,
             created_at INTEGER NOT NULL,
             updated_at INTEGER NOT NULL
         )",
         [],
     )
-    .map_err(|e| format!("Failed to create table: {}", e))?;
+    .map_err(|e| SmelterError::Cache(format!("Failed to create table: {}", e)))?;
 
     // Add file_size column if it doesn't exist (migration)
     let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN file_size INTEGER NOT NULL DEFAULT 0", []);
 
+    // Add year column if it doesn't exist (migration)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN year INTEGER", []);
+
+    // Add album and track_number columns if they don't exist (migration)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN album TEXT", []);
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN track_number INTEGER", []);
+
+    // Add content_hash column if it doesn't exist (migration, filled in lazily)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN content_hash TEXT", []);
+
+    // Add last_accessed column if it doesn't exist (migration, an increasing counter for LRU eviction)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN last_accessed INTEGER NOT NULL DEFAULT 0", []);
+
+    // Add audio property columns if they don't exist (migration)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN sample_rate INTEGER", []);
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN bit_depth INTEGER", []);
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN channels INTEGER", []);
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN bitrate_kbps INTEGER", []);
+
+    // Add fingerprint column if it doesn't exist (migration, filled in lazily)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN fingerprint BLOB", []);
+
+    // Add genres column if it doesn't exist (migration)
+    let _ = conn.execute("ALTER TABLE audio_metadata ADD COLUMN genres TEXT", []);
+
+    // Add custom_fields column (JSON object of TXXX frames) if it doesn't exist (migration)
+    let _ = conn.execute(
+        "ALTER TABLE audio_metadata ADD COLUMN custom_fields TEXT",
+        [],
+    );
+
     // Create migration tracking table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS migrations (
@@ -97,36 +200,66 @@ pub fn init_database() -> Result<(), String> {
         )",
         [],
     )
-    .map_err(|e| format!("Failed to create migrations table: {}", e))?;
+    .map_err(|e| SmelterError::Cache(format!("Failed to create migrations table: {}", e)))?;
 
-    // Run cache clear migration (one-time to clear stale data from old schema)
-    run_cache_clear_migration(&conn)?;
+    // Run cache clear migrations (one-time to clear stale data from old schema)
+    run_cache_clear_migration(&conn, "clear_stale_cache_v2")?;
+    // Re-read files so the new year column gets populated
+    run_cache_clear_migration(&conn, "add_year_v3")?;
+    run_cache_clear_migration(&conn, "add_album_track_v4")?;
+    run_cache_clear_migration(&conn, "add_audio_properties_v5")?;
+    // Re-read files so ID3v1 genre codes get mapped to names
+    run_cache_clear_migration(&conn, "normalize_genre_v6")?;
+    // Re-read files so decimal and range BPM values are picked up
+    run_cache_clear_migration(&conn, "tolerant_bpm_v7")?;
+    run_cache_clear_migration(&conn, "add_genres_v8")?;
+    run_cache_clear_migration(&conn, "add_custom_fields_v9")?;
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_file_path ON audio_metadata(file_path)",
         [],
     )
-    .map_err(|e| format!("Failed to create index: {}", e))?;
+    .map_err(|e| SmelterError::Cache(format!("Failed to create index: {}", e)))?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_last_accessed ON audio_metadata(last_accessed)",
+        [],
+    )
+    .map_err(|e| SmelterError::Cache(format!("Failed to create index: {}", e)))?;
 
     // Store connection for reuse
-    let mut db = DB.lock().unwrap();
     *db = Some(conn);
 
     Ok(())
 }
 
-/// Get a database connection
-fn get_connection() -> Result<Connection, String> {
-    let db_path = get_db_path();
-    Connection::open(&db_path)
-        .map_err(|e| format!("Failed to open database: {}", e))
+/// Shared database connection, holding the DB lock while in use
+struct ConnectionGuard(MutexGuard<'static, Option<Connection>>);
+
+impl Deref for ConnectionGuard {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        // get_connection only hands out guards for an initialized connection
+        self.0.as_ref().expect("database connection not initialized")
+    }
 }
 
-/// Get cached metadata for a file
-pub fn get_cached_metadata(file_path: &str) -> Result<Option<AudioMetadata>, String> {
-    let conn = get_connection()?;
+/// Get the shared database connection (initializing the database on first use)
+fn get_connection() -> Result<ConnectionGuard, SmelterError> {
+    if lock_db().is_none() {
+        init_database()?;
+    }
 
-    // Get file modification time and size
+    let db = lock_db();
+    if db.is_none() {
+        return Err(SmelterError::Cache("Database not initialized".to_string()));
+    }
+    Ok(ConnectionGuard(db))
+}
+
+/// Get a file's modification time (unix seconds) and size, or zeros if unavailable
+fn file_stat(file_path: &str) -> (i64, i64) {
     let file_meta = std::fs::metadata(file_path).ok();
     let file_modified = file_meta
         .as_ref()
@@ -135,19 +268,80 @@ pub fn get_cached_metadata(file_path: &str) -> Result<Option<AudioMetadata>, Str
         .map(|d| d.as_secs() as i64)
         .unwrap_or(0);
     let file_size = file_meta.map(|m| m.len() as i64).unwrap_or(0);
+    (file_modified, file_size)
+}
+
+/// Check whether a file has a cache entry matching its current mtime and size
+/// Cheaper than get_cached_metadata when the metadata itself isn't needed
+pub fn is_fresh(file_path: &str) -> Result<bool, SmelterError> {
+    let conn = get_connection()?;
+    is_fresh_in(&conn, file_path)
+}
+
+/// Check each file against its cache entry by mtime and size, without reading any tags
+pub fn check_freshness(paths: &[String]) -> Result<Vec<CacheFreshness>, SmelterError> {
+    let conn = get_connection()?;
+
+    paths
+        .iter()
+        .map(|path| {
+            let status = if !std::path::Path::new(path).exists() {
+                FreshnessStatus::Missing
+            } else if is_fresh_in(&conn, path)? {
+                FreshnessStatus::Fresh
+            } else {
+                FreshnessStatus::Stale
+            };
+            Ok(CacheFreshness {
+                path: path.clone(),
+                status,
+            })
+        })
+        .collect()
+}
+
+/// is_fresh on an already locked connection
+fn is_fresh_in(conn: &Connection, file_path: &str) -> Result<bool, SmelterError> {
+    let (file_modified, file_size) = file_stat(file_path);
+
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM audio_metadata
+         WHERE file_path = ?1 AND file_modified = ?2 AND file_size = ?3",
+        rusqlite::params![file_path, file_modified, file_size],
+        |row| row.get(0),
+    )
+    .map_err(|e| SmelterError::Cache(format!("Database error: {}", e)))
+}
+
+/// Get cached metadata for a file
+/// With `validate_by_hash`, an entry whose mtime changed but size didn't (e.g. a touched file)
+/// is kept if the content hash still matches
+pub fn get_cached_metadata(
+    file_path: &str,
+    validate_by_hash: bool,
+) -> Result<Option<AudioMetadata>, SmelterError> {
+    // Get file modification time and size
+    let (file_modified, file_size) = file_stat(file_path);
+
+    // A hit in memory needs no database connection at all
+    if let Some(entry) = lock_memory().get(file_path) {
+        if entry.file_modified == file_modified && entry.file_size == file_size {
+            return Ok(Some(entry.metadata.clone()));
+        }
+    }
+
+    let conn = get_connection()?;
 
-    let result: SqliteResult<AudioMetadata> = conn.query_row(
-        "SELECT file_path, title, artist, genre, mood, energy, bpm, duration_secs, file_modified, file_size
+    // Read the row as-is; validating it may mean hashing the file, which must not hold the DB lock
+    let result: SqliteResult<(i64, i64, Option<String>, AudioMetadata)> = conn.query_row(
+        "SELECT file_path, title, artist, genre, mood, energy, bpm, duration_secs, file_modified, file_size, year, album, track_number, content_hash,
+                sample_rate, bit_depth, channels, bitrate_kbps, genres, custom_fields
          FROM audio_metadata WHERE file_path = ?1",
         [file_path],
         |row| {
             let cached_modified: i64 = row.get(8)?;
             let cached_size: i64 = row.get::<_, Option<i64>>(9)?.unwrap_or(0);
-
-            // Check if cache is still valid (both mtime and size must match)
-            if cached_modified != file_modified || cached_size != file_size {
-                return Err(rusqlite::Error::QueryReturnedNoRows);
-            }
+            let cached_hash: Option<String> = row.get(13)?;
 
             let path: String = row.get(0)?;
             let filename = std::path::Path::new(&path)
@@ -156,40 +350,252 @@ pub fn get_cached_metadata(file_path: &str) -> Result<Option<AudioMetadata>, Str
                 .unwrap_or("Unknown")
                 .to_string();
 
-            Ok(AudioMetadata {
+            let metadata = AudioMetadata {
                 path,
                 filename,
+                is_sfx: false,
                 title: row.get(1)?,
                 artist: row.get(2)?,
                 genre: row.get(3)?,
+                genres: row
+                    .get::<_, Option<String>>(18)?
+                    .map(|joined| split_genres(&joined))
+                    .unwrap_or_default(),
                 mood: row.get(4)?,
+                mood_source: None,
                 energy: row.get(5)?,
+                energy_level: row
+                    .get::<_, Option<String>>(5)?
+                    .as_deref()
+                    .and_then(parse_energy_level),
                 bpm: row.get::<_, Option<i32>>(6)?.map(|v| v as u32),
                 duration_secs: row.get(7)?,
+                duration_display: row.get::<_, Option<f64>>(7)?.map(format_duration),
+                year: row.get::<_, Option<i32>>(10)?.map(|v| v as u32),
+                album: row.get(11)?,
+                track_number: row.get::<_, Option<i32>>(12)?.map(|v| v as u32),
+                file_modified: Some(file_modified),
+                sample_rate: row.get::<_, Option<i32>>(14)?.map(|v| v as u32),
+                bit_depth: row.get::<_, Option<i32>>(15)?.map(|v| v as u8),
+                channels: row.get::<_, Option<i32>>(16)?.map(|v| v as u8),
+                bitrate_kbps: row.get::<_, Option<i32>>(17)?.map(|v| v as u32),
+                custom_fields: custom_fields_from_json(row.get(19)?),
+                cover_art: None,
                 category_override: None,
-            })
+            };
+            Ok((cached_modified, cached_size, cached_hash, metadata))
+        },
+    );
+    drop(conn);
+
+    let (cached_modified, cached_size, cached_hash, metadata) = match result {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) => return Err(SmelterError::Cache(format!("Database error: {}", e))),
+    };
+
+    // Check if cache is still valid (both mtime and size must match)
+    if cached_modified != file_modified || cached_size != file_size {
+        // Only the mtime changed: compare content hashes if requested
+        let unchanged = validate_by_hash
+            && cached_size == file_size
+            && cached_hash.is_some()
+            && hash_file(file_path).ok() == cached_hash;
+        if !unchanged {
+            return Ok(None);
+        }
+
+        // Remember the new mtime so the next lookup doesn't need to hash again
+        // The row may have been rewritten while hashing; then it no longer vouches for the file
+        let conn = get_connection()?;
+        let updated = conn
+            .execute(
+                "UPDATE audio_metadata SET file_modified = ?1
+                 WHERE file_path = ?2 AND file_modified = ?3 AND content_hash = ?4",
+                rusqlite::params![file_modified, file_path, cached_modified, cached_hash],
+            )
+            .map_err(|e| SmelterError::Cache(format!("Database error: {}", e)))?;
+        if updated == 0 {
+            return Ok(None);
+        }
+    }
+
+    // Mark the entry as recently used so eviction drops it last
+    let conn = get_connection()?;
+    conn.execute(
+        &format!(
+            "UPDATE audio_metadata SET last_accessed = {} WHERE file_path = ?1",
+            NEXT_ACCESS
+        ),
+        [file_path],
+    )
+    .map_err(|e| SmelterError::Cache(format!("Database error: {}", e)))?;
+
+    lock_memory().put(
+        file_path.to_string(),
+        MemoryEntry {
+            file_modified,
+            file_size,
+            metadata: metadata.clone(),
         },
     );
+    Ok(Some(metadata))
+}
+
+/// Get a file's content hash, reusing the cached value while the entry is still valid
+/// The hash is only computed (and stored) on a cache miss
+pub fn get_content_hash(file_path: &str) -> Result<String, SmelterError> {
+    let conn = get_connection()?;
+    let (file_modified, file_size) = file_stat(file_path);
+
+    let cached: Option<String> = conn
+        .query_row(
+            "SELECT content_hash FROM audio_metadata
+             WHERE file_path = ?1 AND file_modified = ?2 AND file_size = ?3",
+            rusqlite::params![file_path, file_modified, file_size],
+            |row| row.get(0),
+        )
+        .unwrap_or(None);
+
+    if let Some(hash) = cached {
+        return Ok(hash);
+    }
+
+    // Reading the whole file is slow, so don't hold the DB lock while hashing
+    drop(conn);
+    let hash = hash_file(file_path)?;
+
+    // Store it on the existing entry (files that were never scanned have no row to update)
+    let conn = get_connection()?;
+    let _ = conn.execute(
+        "UPDATE audio_metadata SET content_hash = ?1
+         WHERE file_path = ?2 AND file_modified = ?3 AND file_size = ?4",
+        rusqlite::params![hash, file_path, file_modified, file_size],
+    );
+
+    Ok(hash)
+}
+
+/// Get a file's acoustic fingerprint, reusing the cached value while the entry is still valid
+/// The fingerprint is only computed (and stored) on a cache miss
+pub fn get_fingerprint(file_path: &str) -> Result<Vec<u32>, SmelterError> {
+    let conn = get_connection()?;
+    let (file_modified, file_size) = file_stat(file_path);
+
+    let cached: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT fingerprint FROM audio_metadata
+             WHERE file_path = ?1 AND file_modified = ?2 AND file_size = ?3",
+            rusqlite::params![file_path, file_modified, file_size],
+            |row| row.get(0),
+        )
+        .unwrap_or(None);
 
-    match result {
-        Ok(metadata) => Ok(Some(metadata)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(format!("Database error: {}", e)),
+    if let Some(bytes) = cached {
+        return Ok(fingerprint::from_bytes(&bytes));
     }
+
+    // Decoding is slow, so don't hold the DB lock while fingerprinting
+    drop(conn);
+    let fingerprint = fingerprint::compute_fingerprint(file_path)?;
+
+    // Store it on the existing entry (files that were never scanned have no row to update)
+    let conn = get_connection()?;
+    let _ = conn.execute(
+        "UPDATE audio_metadata SET fingerprint = ?1
+         WHERE file_path = ?2 AND file_modified = ?3 AND file_size = ?4",
+        rusqlite::params![fingerprint::to_bytes(&fingerprint), file_path, file_modified, file_size],
+    );
+
+    Ok(fingerprint)
 }
 
 /// Cache metadata for a file
-pub fn cache_metadata(metadata: &AudioMetadata) -> Result<(), String> {
+pub fn cache_metadata(metadata: &AudioMetadata) -> Result<(), SmelterError> {
     let conn = get_connection()?;
 
-    let file_meta = std::fs::metadata(&metadata.path).ok();
-    let file_modified = file_meta
-        .as_ref()
-        .and_then(|m| m.modified().ok())
-        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
-    let file_size = file_meta.map(|m| m.len() as i64).unwrap_or(0);
+    insert_metadata(&conn, metadata)
+        .map_err(|e| SmelterError::Cache(format!("Failed to cache metadata: {}", e)))?;
+    forget_in_memory([metadata.path.as_str()]);
+
+    evict_over_limit(&conn)?;
+
+    Ok(())
+}
+
+/// Cache metadata for many files in a single transaction
+/// Rows that fail to insert are skipped; returns how many were cached
+pub fn cache_metadata_batch(items: &[AudioMetadata]) -> Result<u32, SmelterError> {
+    if items.is_empty() {
+        return Ok(0);
+    }
+
+    let conn = get_connection()?;
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| SmelterError::Cache(format!("Failed to start transaction: {}", e)))?;
+
+    let mut count = 0u32;
+    for metadata in items {
+        // A failed statement is rolled back on its own, the transaction stays usable
+        match insert_metadata(&tx, metadata) {
+            Ok(_) => count += 1,
+            Err(e) => eprintln!("Failed to cache {}: {}", metadata.path, e),
+        }
+    }
+
+    tx.commit()
+        .map_err(|e| SmelterError::Cache(format!("Failed to commit cache batch: {}", e)))?;
+    forget_in_memory(items.iter().map(|metadata| metadata.path.as_str()));
+
+    evict_over_limit(&conn)?;
+
+    Ok(count)
+}
+
+/// Set the maximum number of cached entries (0 = unlimited) and evict any excess
+/// Returns the number of evicted entries
+pub fn set_cache_limit(limit: u32) -> Result<u32, SmelterError> {
+    CACHE_LIMIT.store(limit, Ordering::Relaxed);
+
+    let conn = get_connection()?;
+    let evicted = evict_over_limit(&conn)?;
+    if evicted > 0 {
+        vacuum(&conn)?;
+    }
+    Ok(evicted)
+}
+
+/// Next value for the last_accessed column (higher = more recently read or written)
+const NEXT_ACCESS: &str = "(SELECT COALESCE(MAX(last_accessed), 0) + 1 FROM audio_metadata)";
+
+/// Delete the least recently used entries beyond the cache limit
+fn evict_over_limit(conn: &Connection) -> Result<u32, SmelterError> {
+    let limit = CACHE_LIMIT.load(Ordering::Relaxed);
+    if limit == 0 {
+        return Ok(0);
+    }
+
+    let deleted = conn
+        .execute(
+            "DELETE FROM audio_metadata WHERE id IN (
+                SELECT id FROM audio_metadata ORDER BY last_accessed DESC, id DESC LIMIT -1 OFFSET ?1
+            )",
+            [limit],
+        )
+        .map_err(|e| SmelterError::Cache(format!("Failed to evict cache entries: {}", e)))?;
+
+    // Evicted rows could be any paths, so start the memory layer over
+    if deleted > 0 {
+        lock_memory().clear();
+    }
+
+    Ok(deleted as u32)
+}
+
+/// Insert or replace a single metadata row
+fn insert_metadata(conn: &Connection, metadata: &AudioMetadata) -> SqliteResult<usize> {
+    let (file_modified, file_size) = file_stat(&metadata.path);
 
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -197,9 +603,13 @@ pub fn cache_metadata(metadata: &AudioMetadata) -> Result<(), String> {
         .unwrap_or(0);
 
     conn.execute(
-        "INSERT OR REPLACE INTO audio_metadata
-         (file_path, file_modified, file_size, title, artist, genre, mood, energy, bpm, duration_secs, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?11)",
+        &format!(
+            "INSERT OR REPLACE INTO audio_metadata
+             (file_path, file_modified, file_size, title, artist, genre, mood, energy, bpm, duration_secs, year, album, track_number,
+              sample_rate, bit_depth, channels, bitrate_kbps, genres, custom_fields, created_at, updated_at, last_accessed)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?20, {})",
+            NEXT_ACCESS
+        ),
         rusqlite::params![
             metadata.path,
             file_modified,
@@ -211,16 +621,73 @@ pub fn cache_metadata(metadata: &AudioMetadata) -> Result<(), String> {
             metadata.energy,
             metadata.bpm.map(|v| v as i32),
             metadata.duration_secs,
+            metadata.year.map(|v| v as i32),
+            metadata.album,
+            metadata.track_number.map(|v| v as i32),
+            metadata.sample_rate.map(|v| v as i32),
+            metadata.bit_depth.map(|v| v as i32),
+            metadata.channels.map(|v| v as i32),
+            metadata.bitrate_kbps.map(|v| v as i32),
+            metadata.genres.join(GENRE_SEPARATOR),
+            custom_fields_to_json(&metadata.custom_fields),
             now,
         ],
     )
-    .map_err(|e| format!("Failed to cache metadata: {}", e))?;
+}
 
-    Ok(())
+/// Store custom fields as a JSON object (NULL when there are none)
+fn custom_fields_to_json(fields: &HashMap<String, String>) -> Option<String> {
+    if fields.is_empty() {
+        None
+    } else {
+        serde_json::to_string(fields).ok()
+    }
+}
+
+/// Read a stored custom_fields column back into a map
+fn custom_fields_from_json(json: Option<String>) -> HashMap<String, String> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Separator for the genres list stored in one column (genres never contain ";")
+const GENRE_SEPARATOR: &str = "; ";
+
+/// Split a stored genres column back into a list
+fn split_genres(joined: &str) -> Vec<String> {
+    joined
+        .split(GENRE_SEPARATOR)
+        .filter(|g| !g.is_empty())
+        .map(|g| g.to_string())
+        .collect()
+}
+
+/// Get cache statistics (entry count, database size, entry age range)
+pub fn get_cache_stats() -> Result<CacheStats, SmelterError> {
+    let conn = get_connection()?;
+
+    let (entry_count, oldest_entry, newest_entry): (i64, Option<i64>, Option<i64>) = conn
+        .query_row(
+            "SELECT COUNT(*), MIN(updated_at), MAX(updated_at) FROM audio_metadata",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|e| SmelterError::Cache(format!("Failed to read cache stats: {}", e)))?;
+
+    let db_file_bytes = std::fs::metadata(get_db_path())
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    Ok(CacheStats {
+        entry_count: entry_count as u32,
+        db_file_bytes,
+        oldest_entry,
+        newest_entry,
+    })
 }
 
 /// Clear all cached metadata
-pub fn clear_cache() -> Result<u32, String> {
+pub fn clear_cache() -> Result<u32, SmelterError> {
     let conn = get_connection()?;
 
     let count: i32 = conn
@@ -228,13 +695,40 @@ pub fn clear_cache() -> Result<u32, String> {
         .unwrap_or(0);
 
     conn.execute("DELETE FROM audio_metadata", [])
-        .map_err(|e| format!("Failed to clear cache: {}", e))?;
+        .map_err(|e| SmelterError::Cache(format!("Failed to clear cache: {}", e)))?;
+    lock_memory().clear();
+
+    // Shrink the file now that it's (mostly) empty
+    vacuum(&conn)?;
 
     Ok(count as u32)
 }
 
+/// Reclaim unused space in the cache database file
+/// Returns how many bytes the file shrank by
+pub fn vacuum_cache() -> Result<u64, SmelterError> {
+    let conn = get_connection()?;
+    vacuum(&conn)
+}
+
+/// Run VACUUM and report the reclaimed bytes
+fn vacuum(conn: &Connection) -> Result<u64, SmelterError> {
+    let db_path = get_db_path();
+    let size_before = std::fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+
+    // VACUUM can't run inside a transaction, so it runs as its own statement
+    conn.execute("VACUUM", [])
+        .map_err(|e| SmelterError::Cache(format!("Failed to vacuum cache: {}", e)))?;
+
+    // Fold the WAL back into the main file so the new size is visible
+    let _ = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()));
+
+    let size_after = std::fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+    Ok(size_before.saturating_sub(size_after))
+}
+
 /// Clear cached metadata for specific files
-pub fn clear_cache_for_files(file_paths: &[String]) -> Result<u32, String> {
+pub fn clear_cache_for_files(file_paths: &[String]) -> Result<u32, SmelterError> {
     if file_paths.is_empty() {
         return Ok(0);
     }
@@ -248,6 +742,437 @@ pub fn clear_cache_for_files(file_paths: &[String]) -> Result<u32, String> {
             count += n as u32;
         }
     }
+    forget_in_memory(file_paths.iter().map(String::as_str));
 
     Ok(count)
 }
+
+/// One cache row in an export file
+/// Keeps the source machine's mtime/size so entries stay valid only while the file is unchanged
+/// (files missing on the importing machine never match and are re-read on access)
+#[derive(Serialize, Deserialize)]
+struct ExportedEntry {
+    file_path: String,
+    file_modified: i64,
+    file_size: i64,
+    title: Option<String>,
+    artist: Option<String>,
+    genre: Option<String>,
+    #[serde(default)]
+    genres: Vec<String>,
+    #[serde(default)]
+    custom_fields: HashMap<String, String>,
+    mood: Option<String>,
+    energy: Option<String>,
+    bpm: Option<u32>,
+    duration_secs: Option<f64>,
+    year: Option<u32>,
+    album: Option<String>,
+    track_number: Option<u32>,
+    content_hash: Option<String>,
+    sample_rate: Option<u32>,
+    bit_depth: Option<u8>,
+    channels: Option<u8>,
+    bitrate_kbps: Option<u32>,
+}
+
+/// Write every cached entry to a JSON file (fingerprints are left out; they're recomputed on demand)
+/// Returns how many entries were exported
+pub fn export_cache(path: &str) -> Result<u32, SmelterError> {
+    let conn = get_connection()?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT file_path, file_modified, file_size, title, artist, genre, genres, mood, energy, bpm, duration_secs,
+                    year, album, track_number, content_hash, sample_rate, bit_depth, channels, bitrate_kbps, custom_fields
+             FROM audio_metadata ORDER BY file_path",
+        )
+        .map_err(|e| SmelterError::Cache(format!("Failed to export cache: {}", e)))?;
+    let entries: Vec<ExportedEntry> = stmt
+        .query_map([], |row| {
+            Ok(ExportedEntry {
+                file_path: row.get(0)?,
+                file_modified: row.get(1)?,
+                file_size: row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                title: row.get(3)?,
+                artist: row.get(4)?,
+                genre: row.get(5)?,
+                genres: row
+                    .get::<_, Option<String>>(6)?
+                    .map(|joined| split_genres(&joined))
+                    .unwrap_or_default(),
+                mood: row.get(7)?,
+                energy: row.get(8)?,
+                bpm: row.get::<_, Option<i32>>(9)?.map(|v| v as u32),
+                duration_secs: row.get(10)?,
+                year: row.get::<_, Option<i32>>(11)?.map(|v| v as u32),
+                album: row.get(12)?,
+                track_number: row.get::<_, Option<i32>>(13)?.map(|v| v as u32),
+                content_hash: row.get(14)?,
+                sample_rate: row.get::<_, Option<i32>>(15)?.map(|v| v as u32),
+                bit_depth: row.get::<_, Option<i32>>(16)?.map(|v| v as u8),
+                channels: row.get::<_, Option<i32>>(17)?.map(|v| v as u8),
+                bitrate_kbps: row.get::<_, Option<i32>>(18)?.map(|v| v as u32),
+                custom_fields: custom_fields_from_json(row.get(19)?),
+            })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| SmelterError::Cache(format!("Failed to export cache: {}", e)))?;
+
+    let json = serde_json::to_string_pretty(&entries)
+        .map_err(|e| SmelterError::Other(format!("Failed to export cache: {}", e)))?;
+    std::fs::write(path, json).map_err(|e| SmelterError::from_io(&e, path, "write"))?;
+
+    Ok(entries.len() as u32)
+}
+
+/// Load entries from a file written by export_cache, replacing cached entries for the same paths
+/// Returns how many entries were imported
+pub fn import_cache(path: &str) -> Result<u32, SmelterError> {
+    let json = std::fs::read_to_string(path).map_err(|e| SmelterError::from_io(&e, path, "read"))?;
+    let entries: Vec<ExportedEntry> = serde_json::from_str(&json)
+        .map_err(|e| SmelterError::InvalidInput(format!("Not a cache export file: {}", e)))?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    let conn = get_connection()?;
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| SmelterError::Cache(format!("Failed to start transaction: {}", e)))?;
+
+    for entry in &entries {
+        tx.execute(
+            &format!(
+                "INSERT OR REPLACE INTO audio_metadata
+                 (file_path, file_modified, file_size, title, artist, genre, mood, energy, bpm, duration_secs, year, album, track_number,
+                  content_hash, sample_rate, bit_depth, channels, bitrate_kbps, genres, custom_fields, created_at, updated_at, last_accessed)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?21, {})",
+                NEXT_ACCESS
+            ),
+            rusqlite::params![
+                entry.file_path,
+                entry.file_modified,
+                entry.file_size,
+                entry.title,
+                entry.artist,
+                entry.genre,
+                entry.mood,
+                entry.energy,
+                entry.bpm.map(|v| v as i32),
+                entry.duration_secs,
+                entry.year.map(|v| v as i32),
+                entry.album,
+                entry.track_number.map(|v| v as i32),
+                entry.content_hash,
+                entry.sample_rate.map(|v| v as i32),
+                entry.bit_depth.map(|v| v as i32),
+                entry.channels.map(|v| v as i32),
+                entry.bitrate_kbps.map(|v| v as i32),
+                entry.genres.join(GENRE_SEPARATOR),
+                custom_fields_to_json(&entry.custom_fields),
+                now,
+            ],
+        )
+        .map_err(|e| SmelterError::Cache(format!("Failed to import '{}': {}", entry.file_path, e)))?;
+    }
+
+    tx.commit()
+        .map_err(|e| SmelterError::Cache(format!("Failed to commit cache import: {}", e)))?;
+    forget_in_memory(entries.iter().map(|entry| entry.file_path.as_str()));
+
+    evict_over_limit(&conn)?;
+
+    Ok(entries.len() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::fs::File;
+    use std::time::Duration;
+
+    /// Set a file's modification time to `secs` after the epoch without changing its contents
+    fn touch(path: &str, secs: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn touched_but_unchanged_file_hits_cache_in_hash_mode() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        let dir = test_support::temp_dir("cache_hash");
+        let path = test_support::write_file(&dir, "ES_Loop.wav", b"loop");
+        touch(&path, 1_000_000);
+        let metadata = AudioMetadata {
+            path: path.clone(),
+            filename: "ES_Loop.wav".to_string(),
+            title: Some("Loop".to_string()),
+            ..Default::default()
+        };
+        cache_metadata(&metadata).unwrap();
+        get_content_hash(&path).unwrap();
+
+        touch(&path, 2_000_000);
+
+        assert!(get_cached_metadata(&path, false).unwrap().is_none());
+        let cached = get_cached_metadata(&path, true).unwrap().unwrap();
+        assert_eq!(cached.title.as_deref(), Some("Loop"));
+        // The new mtime was stored, so the next plain lookup hits too
+        assert!(get_cached_metadata(&path, false).unwrap().is_some());
+    }
+
+    #[test]
+    fn batch_of_a_thousand_rows_reads_back() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        let dir = test_support::temp_dir("cache_batch");
+        // The files don't exist, so they're cached (and validated) with zero mtime and size
+        let items: Vec<AudioMetadata> = (0..1000)
+            .map(|index| AudioMetadata {
+                path: dir
+                    .join(format!("ES_{}.wav", index))
+                    .to_string_lossy()
+                    .to_string(),
+                filename: format!("ES_{}.wav", index),
+                bpm: Some(index),
+                ..Default::default()
+            })
+            .collect();
+
+        assert_eq!(cache_metadata_batch(&items).unwrap(), 1000);
+
+        for item in &items {
+            let cached = get_cached_metadata(&item.path, false).unwrap().unwrap();
+            assert_eq!(cached.bpm, item.bpm);
+        }
+    }
+
+    #[test]
+    fn eviction_drops_the_least_recently_read_entry() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        clear_cache().unwrap();
+        let dir = test_support::temp_dir("cache_lru");
+        let paths: Vec<String> = (0..3)
+            .map(|index| {
+                dir.join(format!("ES_{}.wav", index))
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        for path in &paths {
+            let metadata = AudioMetadata {
+                path: path.clone(),
+                ..Default::default()
+            };
+            cache_metadata(&metadata).unwrap();
+        }
+
+        // Reading the oldest entry makes the second one the least recently used
+        assert!(get_cached_metadata(&paths[0], false).unwrap().is_some());
+        let evicted = set_cache_limit(2).unwrap();
+        set_cache_limit(DEFAULT_CACHE_LIMIT).unwrap();
+
+        assert_eq!(evicted, 1);
+        assert!(get_cached_metadata(&paths[0], false).unwrap().is_some());
+        assert!(get_cached_metadata(&paths[1], false).unwrap().is_none());
+        assert!(get_cached_metadata(&paths[2], false).unwrap().is_some());
+    }
+
+    #[test]
+    fn stats_count_the_cached_entries() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        clear_cache().unwrap();
+        let dir = test_support::temp_dir("cache_stats");
+        for index in 0..3 {
+            let metadata = AudioMetadata {
+                path: dir
+                    .join(format!("ES_{}.wav", index))
+                    .to_string_lossy()
+                    .to_string(),
+                ..Default::default()
+            };
+            cache_metadata(&metadata).unwrap();
+        }
+
+        let stats = get_cache_stats().unwrap();
+
+        assert_eq!(stats.entry_count, 3);
+        assert!(stats.db_file_bytes > 0);
+        assert!(stats.oldest_entry.is_some());
+        assert!(stats.oldest_entry <= stats.newest_entry);
+    }
+
+    #[test]
+    fn clearing_a_populated_cache_shrinks_the_file() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        let dir = test_support::temp_dir("cache_vacuum");
+        let items: Vec<AudioMetadata> = (0..2000)
+            .map(|index| AudioMetadata {
+                path: dir
+                    .join(format!("ES_{}.wav", index))
+                    .to_string_lossy()
+                    .to_string(),
+                title: Some("A fairly long title to take up some room ".repeat(4)),
+                ..Default::default()
+            })
+            .collect();
+        cache_metadata_batch(&items).unwrap();
+        // Move the new rows out of the WAL so they count towards the file size
+        get_connection()
+            .unwrap()
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .unwrap();
+        let size_before = std::fs::metadata(get_db_path()).unwrap().len();
+
+        clear_cache().unwrap();
+
+        let size_after = std::fs::metadata(get_db_path()).unwrap().len();
+        assert!(
+            size_after < size_before,
+            "{} >= {}",
+            size_after,
+            size_before
+        );
+    }
+
+    #[test]
+    fn database_lives_in_the_overridden_data_dir() {
+        let _databases = test_support::use_test_databases();
+        let data = PathBuf::from(std::env::var_os(data_dir::DATA_DIR_ENV).unwrap());
+
+        assert_eq!(get_db_path(), data.join("smelter_cache.db"));
+    }
+
+    #[test]
+    fn genres_list_round_trips_through_the_cache() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        let dir = test_support::temp_dir("cache_genres");
+        let path = test_support::write_file(&dir, "ES_Mixed.wav", b"audio");
+        let metadata = AudioMetadata {
+            path: path.clone(),
+            filename: "ES_Mixed.wav".to_string(),
+            genres: vec!["Rock".to_string(), "Alternative".to_string()],
+            ..Default::default()
+        };
+
+        cache_metadata(&metadata).unwrap();
+
+        let cached = get_cached_metadata(&path, false).unwrap().unwrap();
+        assert_eq!(cached.genres, metadata.genres);
+    }
+
+    /// Cache metadata for a small file in `dir` and return its path
+    fn cache_file(dir: &std::path::Path, name: &str, genre: &str) -> String {
+        let path = test_support::write_file(dir, name, name.as_bytes());
+        cache_metadata(&AudioMetadata {
+            path: path.clone(),
+            filename: name.to_string(),
+            genre: Some(genre.to_string()),
+            genres: vec![genre.to_string()],
+            bpm: Some(120),
+            ..Default::default()
+        })
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn export_clear_import_round_trips_every_row() {
+        let _databases = test_support::use_test_databases();
+        init_database().unwrap();
+        clear_cache().unwrap();
+        let dir = test_support::temp_dir("cache_round_trip");
+        let kick = cache_file(&dir, "ES_Kick.wav", "Rock");
+        cache_file(&dir, "ES_Pad.wav", "Ambient");
+        let exported = dir.join("export.json").to_string_lossy().to_string();
+        let reexported = dir.join("reexport.json").to_string_lossy().to_string();
+
+        assert_eq!(export_cache(&exported).unwrap(), 2);
+        assert_eq!(clear_cache().unwrap(), 2);
+        assert!(get_cached_metadata(&kick, false).unwrap().is_none());
+
+        assert_eq!(import_cache(&exported).unwrap(), 2);
+        export_cache(&reexported).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&exported).unwrap(),
+            std::fs::read_to_string(&reexported).unwrap()
+        );
+        let cached = get_cached_metadata(&kick, false).unwrap().unwrap();
+        assert_eq!(cached.genre.as_deref(), Some("Rock"));
+        assert_eq!(cached.bpm, Some(120));
+    }
+
+    #[test]
+    fn touched_file_reports_stale() {
+        let _databases = test_support::use_test_databases();
+        let dir = test_support::temp_dir("cache_freshness");
+        let fresh = cache_file(&dir, "ES_Fresh.wav", "Rock");
+        let touched = cache_file(&dir, "ES_Touched.wav", "Rock");
+        let deleted = cache_file(&dir, "ES_Deleted.wav", "Rock");
+
+        let modified = std::fs::metadata(&touched).unwrap().modified().unwrap();
+        let later = modified + std::time::Duration::from_secs(60);
+        filetime::set_file_mtime(&touched, filetime::FileTime::from_system_time(later)).unwrap();
+        std::fs::remove_file(&deleted).unwrap();
+
+        let statuses: Vec<FreshnessStatus> = check_freshness(&[fresh, touched, deleted])
+            .unwrap()
+            .into_iter()
+            .map(|freshness| freshness.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                FreshnessStatus::Fresh,
+                FreshnessStatus::Stale,
+                FreshnessStatus::Missing
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_hit_is_dropped_once_the_file_is_touched() {
+        let _databases = test_support::use_test_databases();
+        let dir = test_support::temp_dir("memory_cache_touch");
+        let path = cache_file(&dir, "ES_Memory.wav", "Rock");
+        assert!(get_cached_metadata(&path, false).unwrap().is_some());
+
+        // Served from memory: the database row is no longer consulted
+        get_connection()
+            .unwrap()
+            .execute("DELETE FROM audio_metadata WHERE file_path = ?1", [&path])
+            .unwrap();
+        assert!(get_cached_metadata(&path, false).unwrap().is_some());
+
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let later = modified + std::time::Duration::from_secs(60);
+        filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(later)).unwrap();
+        assert!(get_cached_metadata(&path, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn clearing_the_cache_clears_the_memory_layer() {
+        let _databases = test_support::use_test_databases();
+        let dir = test_support::temp_dir("memory_cache_clear");
+        let path = cache_file(&dir, "ES_Cleared.wav", "Rock");
+        assert!(get_cached_metadata(&path, false).unwrap().is_some());
+
+        clear_cache().unwrap();
+
+        assert!(get_cached_metadata(&path, false).unwrap().is_none());
+    }
+}
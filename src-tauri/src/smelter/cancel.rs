@@ -0,0 +1,59 @@
+// Cancellation of long-running scan/organize operations
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Running operations by id (registered by the frontend-supplied operation id)
+lazy_static::lazy_static! {
+    static ref OPERATIONS: Mutex<HashMap<String, CancelToken>> = Mutex::new(HashMap::new());
+}
+
+/// Shared flag checked between files; set when the user cancels the operation
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Request cancellation
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Register a new operation and return its token (replaces any previous one with the same id)
+pub fn register(id: &str) -> CancelToken {
+    let token = CancelToken::default();
+    OPERATIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(id.to_string(), token.clone());
+    token
+}
+
+/// Remove a finished operation
+pub fn unregister(id: &str) {
+    OPERATIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(id);
+}
+
+/// Cancel a running operation
+/// Returns false if no operation with this id is running
+pub fn cancel(id: &str) -> bool {
+    match OPERATIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(id)
+    {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
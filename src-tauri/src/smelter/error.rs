@@ -0,0 +1,134 @@
+// Structured errors for the Smelter
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::io::ErrorKind;
+
+/// An error from scanning, reading tags, caching, organizing or telemetry
+/// Each variant carries the user-facing message; the variant name is the `kind` the frontend
+/// can branch on. Crosses the Tauri boundary as `{ kind, message }`
+#[derive(Debug, thiserror::Error)]
+pub enum SmelterError {
+    /// The OS refused access to a file or folder
+    #[error("{0}")]
+    PermissionDenied(String),
+    /// A file or folder doesn't exist (anymore)
+    #[error("{0}")]
+    NotFound(String),
+    /// Something is already in the way at the destination
+    #[error("{0}")]
+    AlreadyExists(String),
+    /// The destination drive is full (or over quota)
+    #[error("{0}")]
+    DiskFull(String),
+    /// A move can't cross from one drive to another
+    #[error("{0}")]
+    CrossesDevices(String),
+    /// Any other filesystem error
+    #[error("{0}")]
+    Io(String),
+    /// The file is empty or isn't audio in any format we can identify
+    #[error("{0}")]
+    Corrupt(String),
+    /// The file's audio data or tags couldn't be read or written
+    #[error("{0}")]
+    Metadata(String),
+    /// The metadata cache database failed
+    #[error("{0}")]
+    Cache(String),
+    /// The telemetry queue database failed
+    #[error("{0}")]
+    Telemetry(String),
+    /// A bad argument (unknown field, invalid glob pattern, ...)
+    #[error("{0}")]
+    InvalidInput(String),
+    /// Anything that doesn't fit the kinds above
+    #[error("{0}")]
+    Other(String),
+}
+
+impl SmelterError {
+    /// Build a user-facing error for a failed filesystem `operation` (e.g. "move") on `path`
+    pub fn from_io(e: &std::io::Error, path: &str, operation: &str) -> Self {
+        match e.kind() {
+            ErrorKind::PermissionDenied => SmelterError::PermissionDenied(format!(
+                "Permission denied: Cannot {} '{}'. Try choosing a different folder or check folder permissions.",
+                operation, path
+            )),
+            ErrorKind::NotFound => SmelterError::NotFound(format!(
+                "File not found: '{}' may have been moved or deleted.",
+                path
+            )),
+            ErrorKind::AlreadyExists => {
+                SmelterError::AlreadyExists(format!("File already exists at destination: '{}'", path))
+            }
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => SmelterError::DiskFull(format!(
+                "Not enough disk space to {} '{}'.",
+                operation, path
+            )),
+            ErrorKind::CrossesDevices => SmelterError::CrossesDevices(format!(
+                "Cannot {} '{}' across drives. Choose an output folder on the same drive or use copy instead.",
+                operation, path
+            )),
+            _ => SmelterError::Io(format!("Failed to {} '{}': {}", operation, path, e)),
+        }
+    }
+
+    /// The variant name, sent to the frontend as `kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SmelterError::PermissionDenied(_) => "PermissionDenied",
+            SmelterError::NotFound(_) => "NotFound",
+            SmelterError::AlreadyExists(_) => "AlreadyExists",
+            SmelterError::DiskFull(_) => "DiskFull",
+            SmelterError::CrossesDevices(_) => "CrossesDevices",
+            SmelterError::Io(_) => "Io",
+            SmelterError::Corrupt(_) => "Corrupt",
+            SmelterError::Metadata(_) => "Metadata",
+            SmelterError::Cache(_) => "Cache",
+            SmelterError::Telemetry(_) => "Telemetry",
+            SmelterError::InvalidInput(_) => "InvalidInput",
+            SmelterError::Other(_) => "Other",
+        }
+    }
+}
+
+impl Serialize for SmelterError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SmelterError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smelter::hash::hash_file;
+    use crate::test_support;
+
+    #[test]
+    fn permission_denied_serializes_with_its_kind() {
+        let io_error = std::io::Error::from(ErrorKind::PermissionDenied);
+        let error = SmelterError::from_io(&io_error, "/music/ES_Rise.wav", "read");
+
+        let json = serde_json::to_value(error).unwrap();
+
+        assert_eq!(json["kind"], "PermissionDenied");
+        assert_eq!(
+            json["message"],
+            "Permission denied: Cannot read '/music/ES_Rise.wav'. Try choosing a different folder or check folder permissions."
+        );
+        assert_eq!(json.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn hashing_a_missing_file_is_not_found() {
+        let dir = test_support::temp_dir("error_hash_missing");
+        let path = dir.join("ES_Gone.wav");
+
+        let err = hash_file(path.to_str().unwrap()).unwrap_err();
+
+        assert_eq!(err.kind(), "NotFound");
+    }
+}
@@ -0,0 +1,198 @@
+// Acoustic fingerprints for finding re-encoded copies of the same track
+use rusty_chromaprint::{Configuration, Fingerprinter};
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use super::SmelterError;
+
+/// Only the start of each file is fingerprinted (enough to identify a track, much faster)
+const FINGERPRINT_SECONDS: u64 = 120;
+
+/// How far (in fingerprint items, ~0.12s each) the two fingerprints may be shifted when comparing
+const MAX_ALIGNMENT_OFFSET: usize = 16;
+
+/// Minimum number of overlapping items for a comparison to count
+const MIN_OVERLAP: usize = 16;
+
+/// Decode the start of an audio file and compute its Chromaprint fingerprint
+pub fn compute_fingerprint(path: &str) -> Result<Vec<u32>, SmelterError> {
+    let file = File::open(path).map_err(|e| SmelterError::from_io(&e, path, "open"))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| SmelterError::Metadata(format!("Unsupported audio format: {}", e)))?;
+    let mut format = probed.format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| SmelterError::Metadata("No audio track found".to_string()))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| SmelterError::Metadata("Unknown sample rate".to_string()))?;
+    let channels = track
+        .codec_params
+        .channels
+        .map(|c| c.count() as u32)
+        .ok_or_else(|| SmelterError::Metadata("Unknown channel layout".to_string()))?;
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| SmelterError::Metadata(format!("Unsupported codec: {}", e)))?;
+
+    let config = Configuration::preset_test2();
+    let mut printer = Fingerprinter::new(&config);
+    printer
+        .start(sample_rate, channels)
+        .map_err(|e| SmelterError::Metadata(format!("Failed to start fingerprinting: {:?}", e)))?;
+
+    let sample_limit = u64::from(sample_rate) * u64::from(channels) * FINGERPRINT_SECONDS;
+    let mut consumed = 0u64;
+
+    while consumed < sample_limit {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // End of stream (or a broken tail): fingerprint what was decoded
+            Err(_) => break,
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt frame is skipped rather than failing the whole file
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => {
+                return Err(SmelterError::Metadata(format!(
+                    "Failed to decode audio: {}",
+                    e
+                )))
+            }
+        };
+
+        let mut samples = SampleBuffer::<i16>::new(decoded.capacity() as u64, *decoded.spec());
+        samples.copy_interleaved_ref(decoded);
+        printer.consume(samples.samples());
+        consumed += samples.samples().len() as u64;
+    }
+
+    printer.finish();
+    Ok(printer.fingerprint().to_vec())
+}
+
+/// Compare two fingerprints, returning a similarity between 0.0 (unrelated) and 1.0 (identical)
+/// Small offsets are tried so a few milliseconds of leading silence don't break the match
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let mut best = 0.0f64;
+
+    for offset in 0..=MAX_ALIGNMENT_OFFSET {
+        for (x, y) in [(a, b), (b, a)] {
+            if offset >= x.len() {
+                continue;
+            }
+            let x = &x[offset..];
+            let overlap = x.len().min(y.len());
+            if overlap < MIN_OVERLAP {
+                continue;
+            }
+
+            let differing_bits: u32 = x
+                .iter()
+                .zip(y)
+                .map(|(p, q)| (p ^ q).count_ones())
+                .sum();
+            let score = 1.0 - f64::from(differing_bits) / (overlap as f64 * 32.0);
+            best = best.max(score);
+        }
+    }
+
+    best
+}
+
+/// Serialize a fingerprint for storage in the cache
+pub fn to_bytes(fingerprint: &[u32]) -> Vec<u8> {
+    fingerprint.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Deserialize a fingerprint stored with to_bytes
+pub fn from_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::f64::consts::TAU;
+
+    /// Render a 20 second melody (a new note every half second) at `sample_rate`
+    fn melody(notes: &[f64], sample_rate: u32, volume: f64) -> Vec<i16> {
+        (0..sample_rate * 20)
+            .map(|i| {
+                let t = f64::from(i) / f64::from(sample_rate);
+                let note = notes[(t * 2.0) as usize % notes.len()];
+                let wave = (TAU * note * t).sin() + 0.5 * (TAU * note * 2.0 * t).sin();
+                (wave / 1.5 * volume * f64::from(i16::MAX)) as i16
+            })
+            .collect()
+    }
+
+    const TUNE: [f64; 8] = [261.6, 329.6, 392.0, 523.3, 440.0, 349.2, 293.7, 246.9];
+    const OTHER_TUNE: [f64; 5] = [880.0, 622.3, 987.8, 554.4, 739.9];
+
+    #[test]
+    fn reencoded_copy_matches_and_a_different_track_does_not() {
+        let dir = test_support::temp_dir("fingerprint");
+        let original = dir.join("original.wav");
+        let reencoded = dir.join("reencoded.wav");
+        let different = dir.join("different.wav");
+        test_support::write_wav_samples(&original, 44100, &melody(&TUNE, 44100, 0.8));
+        // Lower sample rate and volume, as a cheaper re-encode would have
+        test_support::write_wav_samples(&reencoded, 22050, &melody(&TUNE, 22050, 0.6));
+        test_support::write_wav_samples(&different, 44100, &melody(&OTHER_TUNE, 44100, 0.8));
+
+        let fingerprint = |path: &Path| compute_fingerprint(path.to_str().unwrap()).unwrap();
+        let original = fingerprint(&original);
+
+        assert!(similarity(&original, &fingerprint(&reencoded)) >= 0.85);
+        assert!(similarity(&original, &fingerprint(&different)) < 0.85);
+    }
+
+    #[test]
+    fn similarity_tolerates_a_small_offset() {
+        let fingerprint: Vec<u32> = (0..64u32).map(|i| i.wrapping_mul(2_654_435_761)).collect();
+
+        assert_eq!(similarity(&fingerprint, &fingerprint), 1.0);
+        assert_eq!(similarity(&fingerprint[3..], &fingerprint), 1.0);
+        assert_eq!(
+            similarity(&fingerprint[..8], &fingerprint),
+            0.0,
+            "too little overlap"
+        );
+    }
+
+    #[test]
+    fn fingerprints_round_trip_through_bytes() {
+        let fingerprint = vec![0, 1, u32::MAX, 0xDEAD_BEEF];
+
+        assert_eq!(from_bytes(&to_bytes(&fingerprint)), fingerprint);
+    }
+}
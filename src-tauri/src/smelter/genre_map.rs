@@ -0,0 +1,104 @@
+// User-provided mapping of raw genres to broader buckets (e.g. "Indie Pop" -> "Pop")
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use super::SmelterError;
+
+// Loaded mapping, keyed by the trimmed, lowercased raw genre
+lazy_static::lazy_static! {
+    static ref GENRE_MAP: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// Normalize a genre for lookup (matching is case-insensitive and ignores surrounding spaces)
+fn lookup_key(genre: &str) -> String {
+    genre.trim().to_lowercase()
+}
+
+/// Load a JSON file of `{ "raw genre": "bucket" }`, replacing any previously loaded mapping
+/// Returns how many genres are mapped
+pub fn load_genre_map(path: &str) -> Result<u32, SmelterError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| SmelterError::from_io(&e, path, "read genre map"))?;
+    let raw: HashMap<String, String> = serde_json::from_str(&json).map_err(|e| {
+        SmelterError::InvalidInput(format!(
+            "Invalid genre map (expected {{\"raw\": \"bucket\"}}): {}",
+            e
+        ))
+    })?;
+
+    let mut map = HashMap::new();
+    for (genre, bucket) in raw {
+        let bucket = bucket.trim();
+        if bucket.is_empty() {
+            return Err(SmelterError::InvalidInput(format!(
+                "Genre '{}' is mapped to an empty bucket",
+                genre
+            )));
+        }
+        map.insert(lookup_key(&genre), bucket.to_string());
+    }
+
+    let count = map.len() as u32;
+    *GENRE_MAP.lock().unwrap_or_else(PoisonError::into_inner) = map;
+    Ok(count)
+}
+
+/// Map a raw genre to its bucket, or return it unchanged if it isn't mapped
+pub fn map_genre(genre: &str) -> String {
+    GENRE_MAP
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&lookup_key(genre))
+        .cloned()
+        .unwrap_or_else(|| genre.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Unloads the genre map when dropped, so a failing test doesn't leak it into others
+    struct ClearGenreMap;
+
+    impl Drop for ClearGenreMap {
+        fn drop(&mut self) {
+            GENRE_MAP
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .clear();
+        }
+    }
+
+    #[test]
+    fn mapped_genres_resolve_to_their_bucket() {
+        let _globals = test_support::lock_globals();
+        let _clear = ClearGenreMap;
+        let dir = test_support::temp_dir("genre_map");
+        // Only genres no other test organizes by, since organize tests don't take the lock
+        let path = test_support::write_file(
+            &dir,
+            "genres.json",
+            br#"{ " indie pop ": "Pop", "Synthwave": "Electronic" }"#,
+        );
+
+        assert_eq!(load_genre_map(&path).unwrap(), 2);
+        assert_eq!(map_genre("Indie Pop"), "Pop");
+        assert_eq!(map_genre("SYNTHWAVE "), "Electronic");
+        assert_eq!(map_genre("Bossa Nova"), "Bossa Nova");
+    }
+
+    #[test]
+    fn empty_buckets_are_rejected() {
+        let _globals = test_support::lock_globals();
+        let _clear = ClearGenreMap;
+        let dir = test_support::temp_dir("genre_map_empty");
+        let path = test_support::write_file(&dir, "genres.json", br#"{ "Indie Pop": " " }"#);
+
+        let err = load_genre_map(&path).unwrap_err();
+
+        assert!(matches!(err, SmelterError::InvalidInput(_)), "{:?}", err);
+        assert!(err.to_string().contains("empty bucket"), "{}", err);
+        assert_eq!(map_genre("Indie Pop"), "Indie Pop");
+    }
+}
@@ -0,0 +1,119 @@
+// Content hashing for duplicate detection
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::SmelterError;
+
+/// Bytes read (and held) per file at a time while hashing
+pub const HASH_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Default memory budget for concurrent hashing, in megabytes
+const DEFAULT_HASH_MEMORY_MB: u32 = 64;
+
+/// Memory budget for concurrent hashing, in megabytes; each hasher holds one chunk
+static HASH_MEMORY_MB: AtomicU32 = AtomicU32::new(DEFAULT_HASH_MEMORY_MB);
+
+/// Hash a file's contents (BLAKE3, hex-encoded), streaming it in `HASH_CHUNK_BYTES` chunks
+pub fn hash_file(path: &str) -> Result<String, SmelterError> {
+    let mut file = File::open(path).map_err(|e| SmelterError::from_io(&e, path, "open"))?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; HASH_CHUNK_BYTES];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                hasher.update(&buffer[..n]);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SmelterError::from_io(&e, path, "read")),
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Set the memory budget for concurrent hashing in megabytes (0 restores the default)
+/// Returns how many files may now be hashed at once
+pub fn set_hash_memory_budget(megabytes: u32) -> u32 {
+    let megabytes = if megabytes == 0 {
+        DEFAULT_HASH_MEMORY_MB
+    } else {
+        megabytes
+    };
+    HASH_MEMORY_MB.store(megabytes, Ordering::Relaxed);
+    max_concurrent_hashers() as u32
+}
+
+/// How many files may be hashed at once: as many chunks as fit the budget, at most one per
+/// core and at least one
+pub fn max_concurrent_hashers() -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    hashers_for_budget(HASH_MEMORY_MB.load(Ordering::Relaxed), cores)
+}
+
+/// Hashers that fit a budget of `megabytes` on a machine with `cores` cores
+fn hashers_for_budget(megabytes: u32, cores: usize) -> usize {
+    let budget = megabytes as usize * 1024 * 1024;
+    (budget / HASH_CHUNK_BYTES).clamp(1, cores)
+}
+
+/// Run `op` on a thread pool sized by `max_concurrent_hashers`, so parallel iterators inside
+/// it never hash more files at once than the memory budget allows
+pub fn with_hash_pool<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(max_concurrent_hashers())
+        .build()
+    {
+        Ok(pool) => pool.install(op),
+        Err(e) => {
+            eprintln!("Failed to create hashing thread pool: {}", e);
+            op()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use rayon::prelude::*;
+
+    #[test]
+    fn concurrent_hashes_match_a_reference_digest() {
+        let dir = test_support::temp_dir("hash_concurrent");
+        // Sizes around the chunk boundary, so partial and multiple chunks are both covered
+        let files: Vec<(String, Vec<u8>)> = [0, 1, HASH_CHUNK_BYTES, HASH_CHUNK_BYTES * 2 + 7]
+            .iter()
+            .enumerate()
+            .map(|(index, &len)| {
+                let contents: Vec<u8> = (0..len).map(|i| (i * 31 + index) as u8).collect();
+                let path = test_support::write_file(&dir, &format!("{}.bin", index), &contents);
+                (path, contents)
+            })
+            .collect();
+
+        let hashes: Vec<String> = with_hash_pool(|| {
+            files
+                .par_iter()
+                .map(|(path, _)| hash_file(path).unwrap())
+                .collect()
+        });
+
+        for ((path, contents), hash) in files.iter().zip(hashes) {
+            assert_eq!(
+                hash,
+                blake3::hash(contents).to_hex().to_string(),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn hasher_count_is_capped_by_the_budget_and_cores() {
+        assert_eq!(hashers_for_budget(64, 8), 8);
+        assert_eq!(hashers_for_budget(3, 8), 3);
+        assert_eq!(hashers_for_budget(1, 8), 1);
+        assert_eq!(hashers_for_budget(64, 1), 1);
+    }
+}
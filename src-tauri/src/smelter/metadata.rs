@@ -1,16 +1,66 @@
 // ID3 tag reading using lofty crate
+use base64::Engine;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use lofty::config::{ParseOptions, ParsingMode, WriteOptions};
+use lofty::error::ErrorKind as LoftyErrorKind;
+use lofty::file::{FileType, TaggedFile};
 use lofty::prelude::*;
 use lofty::probe::Probe;
-use lofty::tag::{ItemKey, TagType};
-use std::path::Path;
+use lofty::tag::{ItemKey, Tag, TagType};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
-use super::AudioMetadata;
+use super::cancel::CancelToken;
+use super::{
+    cache, AudioMetadata, IncrementalScanReport, MoodCommentOptions, ScanOptions, ScanReport,
+    SmelterError,
+};
 
-/// Extract metadata from an audio file (MP3 or WAV)
+/// Read buffer for metadata reads; kept small because parsing mostly seeks from header to
+/// header, and every seek throws the buffer away
+const METADATA_READ_BUFFER: usize = 16 * 1024;
+
+/// Parse options for metadata reads: tags and stream properties only
+/// Embedded pictures (often megabytes) are skipped. Properties come from the stream headers
+/// (WAV fmt chunk, MP3 Xing/VBRI frame, FLAC STREAMINFO, ...), so the audio data itself is
+/// seeked past instead of read, and duration stays exact without decoding frames
+fn metadata_parse_options() -> ParseOptions {
+    ParseOptions::new()
+        .read_properties(true)
+        .read_tags(true)
+        .read_cover_art(false)
+        .parsing_mode(ParsingMode::BestAttempt)
+}
+
+/// Open a file for a metadata read: buffered, typed by its extension (like Probe::open) and
+/// set up with metadata_parse_options
+fn open_for_metadata(path: &str) -> std::io::Result<Probe<BufReader<File>>> {
+    let reader = BufReader::with_capacity(METADATA_READ_BUFFER, File::open(path)?);
+    let mut probe = Probe::new(reader).options(metadata_parse_options());
+    if let Some(file_type) = FileType::from_path(path) {
+        probe.set_file_type(file_type);
+    }
+    Ok(probe)
+}
+
+/// A file's modification time in Unix seconds (None if it can't be read)
+fn file_modified_secs(path: &str) -> Option<i64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let secs = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some(secs as i64)
+}
+
+/// Extract metadata from an audio file (MP3, WAV, FLAC, OGG or M4A)
 /// This is the simple version using basic accessors.
 /// For full ID3v2 frame access (mood, energy, BPM), use read_audio_metadata_full()
 #[allow(dead_code)]
-pub fn read_audio_metadata(path: &str) -> Result<AudioMetadata, String> {
+pub fn read_audio_metadata(path: &str) -> Result<AudioMetadata, SmelterError> {
     let path_obj = Path::new(path);
 
     let filename = path_obj
@@ -19,12 +69,13 @@ pub fn read_audio_metadata(path: &str) -> Result<AudioMetadata, String> {
         .unwrap_or("Unknown")
         .to_string();
 
-    let tagged_file = Probe::open(path)
-        .map_err(|e| format!("Failed to open file: {}", e))?
+    let tagged_file = open_for_metadata(path)
+        .map_err(|e| SmelterError::Metadata(format!("Failed to open file: {}", e)))?
         .read()
-        .map_err(|e| format!("Failed to read file: {}", e))?;
+        .map_err(|e| SmelterError::Metadata(format!("Failed to read file: {}", e)))?;
 
-    let duration_secs = tagged_file.properties().duration().as_secs_f64();
+    let properties = tagged_file.properties();
+    let duration_secs = properties.duration().as_secs_f64();
 
     let tag = tagged_file.primary_tag().or_else(|| tagged_file.first_tag());
 
@@ -41,19 +92,35 @@ pub fn read_audio_metadata(path: &str) -> Result<AudioMetadata, String> {
     Ok(AudioMetadata {
         path: path.to_string(),
         filename,
+        is_sfx: false, // Set by the scan commands (organize::mark_sfx)
         title,
         artist,
         genre,
+        genres: tag.map(read_genres).unwrap_or_default(),
         mood: None,   // Use read_audio_metadata_full for mood
+        mood_source: None,
         energy: None, // Use read_audio_metadata_full for energy
+        energy_level: None,
         bpm: None,    // Use read_audio_metadata_full for BPM
         duration_secs: Some(duration_secs),
+        duration_display: Some(format_duration(duration_secs)),
+        year: tag.and_then(read_year),
+        album: tag.and_then(|t| t.album().map(|s| s.to_string())),
+        track_number: tag.and_then(read_track_number),
+        file_modified: file_modified_secs(path),
+        sample_rate: properties.sample_rate(),
+        bit_depth: properties.bit_depth(),
+        channels: properties.channels(),
+        bitrate_kbps: properties.audio_bitrate(),
+        custom_fields: HashMap::new(),
+        cover_art: None, // Use read_cover_art for embedded art
         category_override: None,
     })
 }
 
 /// Read metadata with full ID3v2 frame access
-pub fn read_audio_metadata_full(path: &str) -> Result<AudioMetadata, String> {
+/// Empty files and files lofty can't identify fail with `SmelterError::Corrupt`
+pub fn read_audio_metadata_full(path: &str) -> Result<AudioMetadata, SmelterError> {
     let path_obj = Path::new(path);
 
     let filename = path_obj
@@ -62,147 +129,1580 @@ pub fn read_audio_metadata_full(path: &str) -> Result<AudioMetadata, String> {
         .unwrap_or("Unknown")
         .to_string();
 
-    let tagged_file = Probe::open(path)
-        .map_err(|e| {
-            let err_str = e.to_string();
-            if err_str.contains("Permission denied") || err_str.contains("permission denied") {
-                format!("Permission denied: Cannot read '{}'. Check file permissions.", filename)
-            } else if err_str.contains("No such file") || err_str.contains("not found") {
-                format!("File not found: '{}' may have been moved or deleted.", filename)
-            } else {
-                format!("Cannot open '{}': {}", filename, e)
-            }
+    // 0-byte stubs (e.g. interrupted downloads) would otherwise fail with a confusing parse error
+    if std::fs::metadata(path).is_ok_and(|meta| meta.len() == 0) {
+        return Err(SmelterError::Corrupt(format!(
+            "'{}' is empty (0 bytes), possibly an interrupted download",
+            filename
+        )));
+    }
+
+    let tagged_file = open_for_metadata(path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::PermissionDenied => SmelterError::PermissionDenied(format!(
+                "Permission denied: Cannot read '{}'. Check file permissions.",
+                filename
+            )),
+            std::io::ErrorKind::NotFound => SmelterError::NotFound(format!(
+                "File not found: '{}' may have been moved or deleted.",
+                filename
+            )),
+            _ => SmelterError::Metadata(format!("Cannot open '{}': {}", filename, e)),
         })?
         .read()
-        .map_err(|e| format!("Cannot read audio data from '{}': {}", filename, e))?;
-
-    let duration_secs = tagged_file.properties().duration().as_secs_f64();
-
-    // Default values
-    let mut title: Option<String> = None;
-    let mut artist: Option<String> = None;
-    let mut genre: Option<String> = None;
-    let mut mood: Option<String> = None;
-    let mut energy: Option<String> = None;
-    let mut bpm: Option<u32> = None;
-
-    // Try ID3v2 tag first for full frame access
-    if let Some(id3v2) = tagged_file.tag(TagType::Id3v2) {
-        title = id3v2.title().map(|s| s.to_string());
-        artist = id3v2.artist().map(|s| s.to_string());
-        genre = id3v2.genre().map(|s| s.to_string());
-
-        // Try multiple sources for mood (in order of priority)
-        // 1. TIT1 - Content group (mood tags in Epidemic Sound)
-        if let Some(item) = id3v2.get(&ItemKey::ContentGroup) {
-            mood = item.value().text().map(|s| s.to_string());
-        }
-        // 2. TMOO - Standard ID3v2.4 mood frame
-        if mood.is_none() {
-            if let Some(item) = id3v2.get(&ItemKey::Mood) {
-                mood = item.value().text().map(|s| s.to_string());
-            }
+        .map_err(|e| match e.kind() {
+            LoftyErrorKind::UnknownFormat => SmelterError::Corrupt(format!(
+                "'{}' is not a valid audio file (unrecognized format)",
+                filename
+            )),
+            _ => SmelterError::Metadata(format!(
+                "Cannot read audio data from '{}': {}",
+                filename, e
+            )),
+        })?;
+
+    let properties = tagged_file.properties();
+    let duration_secs = properties.duration().as_secs_f64();
+
+    // Every field below comes from this one tag, so a file never mixes values from two tags
+    let tag = preferred_tag(&tagged_file);
+    let fields = tag.map(extract_tag_fields).unwrap_or_default();
+    let TagFields {
+        title,
+        artist,
+        genre,
+        mood,
+        mood_source,
+        energy,
+        bpm,
+        custom_fields,
+    } = fields;
+
+    // Older MP3s store ID3v1 genre codes like "(17)" instead of names
+    let genre = genre.map(|g| normalize_genre(&g));
+
+    let genres = tag.map(read_genres).unwrap_or_default();
+
+    // Release year (TDRC/Year for ID3v2, DATE for Vorbis, ©day for MP4)
+    let year = tag.and_then(read_year);
+
+    // Album (TALB / ALBUM) and track number (TRCK / TRACKNUMBER)
+    let album = tag.and_then(|t| t.album().map(|s| s.to_string()));
+    let track_number = tag.and_then(read_track_number);
+
+    Ok(AudioMetadata {
+        path: path.to_string(),
+        filename,
+        is_sfx: false, // Set by the scan commands (organize::mark_sfx)
+        title,
+        artist,
+        genre,
+        genres,
+        mood,
+        mood_source,
+        energy_level: energy.as_deref().and_then(parse_energy_level),
+        energy,
+        bpm,
+        duration_secs: Some(duration_secs),
+        duration_display: Some(format_duration(duration_secs)),
+        year,
+        album,
+        track_number,
+        file_modified: file_modified_secs(path),
+        sample_rate: properties.sample_rate(),
+        bit_depth: properties.bit_depth(),
+        channels: properties.channels(),
+        bitrate_kbps: properties.audio_bitrate(),
+        custom_fields,
+        cover_art: None, // Use read_cover_art for embedded art
+        category_override: None,
+    })
+}
+
+/// The tag metadata is read from: the one with the richest frames
+/// ID3v2 (also embedded in WAV/AIFF), then Vorbis comments, then MP4 atoms; otherwise
+/// e.g. AIFF text chunks or RIFF INFO
+fn preferred_tag(tagged_file: &TaggedFile) -> Option<&Tag> {
+    tagged_file
+        .tag(TagType::Id3v2)
+        .or_else(|| tagged_file.tag(TagType::VorbisComments))
+        .or_else(|| tagged_file.tag(TagType::Mp4Ilst))
+        .or_else(|| tagged_file.primary_tag())
+}
+
+/// Fields read from a single tag, whatever its format
+#[derive(Default)]
+struct TagFields {
+    title: Option<String>,
+    artist: Option<String>,
+    genre: Option<String>,
+    mood: Option<String>,
+    /// Key the mood came from (see AudioMetadata::mood_source)
+    mood_source: Option<String>,
+    energy: Option<String>,
+    bpm: Option<u32>,
+    custom_fields: HashMap<String, String>,
+}
+
+/// Read title/artist/genre plus mood, energy and BPM from any tag, using the keys its
+/// format stores them under (ID3v2 frames, Vorbis comments, MP4 atoms or lofty's generic keys)
+fn extract_tag_fields(tag: &Tag) -> TagFields {
+    let mut fields = TagFields {
+        title: tag.title().map(|s| s.to_string()),
+        artist: tag.artist().map(|s| s.to_string()),
+        genre: tag.genre().map(|s| s.to_string()),
+        ..Default::default()
+    };
+
+    match tag.tag_type() {
+        TagType::Id3v2 => read_id3v2_fields(tag, &mut fields),
+        TagType::VorbisComments => read_vorbis_fields(tag, &mut fields),
+        TagType::Mp4Ilst => read_mp4_fields(tag, &mut fields),
+        _ => read_generic_fields(tag, &mut fields),
+    }
+    fields
+}
+
+/// ID3v2: mood from several frames (Epidemic Sound uses TIT1), energy from TIT3, TBPM
+fn read_id3v2_fields(tag: &Tag, fields: &mut TagFields) {
+    // Try multiple sources for mood (in order of priority)
+    // 1. TIT1 - Content group (mood tags in Epidemic Sound)
+    // 2. TMOO - Standard ID3v2.4 mood frame
+    for (key, source) in [
+        (ItemKey::ContentGroup, "ContentGroup"),
+        (ItemKey::Mood, "Mood"),
+    ] {
+        if let Some(text) = tag.get_string(&key) {
+            fields.mood = Some(text.to_string());
+            fields.mood_source = Some(source.to_string());
+            break;
         }
-        // 3. Comment field (some files store mood here)
-        if mood.is_none() {
-            if let Some(item) = id3v2.get(&ItemKey::Comment) {
-                let comment = item.value().text().map(|s| s.to_string());
-                // Only use comment if it looks like a mood tag (short, no sentences)
-                if let Some(ref c) = comment {
-                    if c.len() < 50 && !c.contains('.') {
-                        mood = comment;
-                    }
-                }
+    }
+
+    // 3. Comment field (some files store mood here)
+    if fields.mood.is_none() {
+        if let Some(comment) = tag.get_string(&ItemKey::Comment) {
+            // Only use comment if it looks like a mood tag (short, no sentences by default)
+            if comment_looks_like_mood(
+                comment,
+                &MOOD_COMMENT_OPTIONS
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner),
+            ) {
+                fields.mood = Some(comment.to_string());
+                fields.mood_source = Some("Comment".to_string());
             }
         }
+    }
 
-        // 4. TXXX custom frames - check for mood-related descriptions
-        if mood.is_none() {
-            for item in id3v2.items() {
-                if let Some(desc) = item.key().map_key(TagType::Id3v2, true) {
-                    let desc_lower = desc.to_lowercase();
-                    if desc_lower.contains("mood") || desc_lower.contains("style") || desc_lower.contains("vibe") {
-                        if let Some(text) = item.value().text() {
-                            mood = Some(text.to_string());
-                            break;
-                        }
+    // 4. TXXX custom frames - check for mood-related descriptions
+    if fields.mood.is_none() {
+        for item in tag.items() {
+            if let Some(desc) = item.key().map_key(TagType::Id3v2, true) {
+                let desc_lower = desc.to_lowercase();
+                if desc_lower.contains("mood") || desc_lower.contains("style") || desc_lower.contains("vibe") {
+                    if let Some(text) = item.value().text() {
+                        fields.mood = Some(text.to_string());
+                        fields.mood_source = Some(format!("TXXX:{}", desc));
+                        break;
                     }
                 }
             }
         }
+    }
 
-        // 5. InitialKey - sometimes used for categorization
-        if mood.is_none() {
-            if let Some(item) = id3v2.get(&ItemKey::InitialKey) {
-                // Only use if it looks like a mood (not a musical key like "C#m")
-                if let Some(text) = item.value().text() {
-                    if !text.contains('#') && !text.contains('m') && text.len() > 3 {
-                        mood = Some(text.to_string());
-                    }
-                }
+    // 5. InitialKey - sometimes used for categorization
+    if fields.mood.is_none() {
+        if let Some(text) = tag.get_string(&ItemKey::InitialKey) {
+            // Only use if it looks like a mood (not a musical key like "C#m" or "8A")
+            if !text.trim().is_empty() && !is_musical_key(text) {
+                fields.mood = Some(text.to_string());
+                fields.mood_source = Some("InitialKey".to_string());
             }
         }
+    }
 
-        // TIT3 - Subtitle (energy level in Epidemic Sound)
-        if let Some(item) = id3v2.get(&ItemKey::TrackSubtitle) {
-            energy = item.value().text().map(|s| s.to_string());
+    // TXXX frames lofty doesn't map to a known key, by description
+    for item in tag.items() {
+        if let (ItemKey::Unknown(desc), Some(text)) = (item.key(), item.value().text()) {
+            fields.custom_fields.insert(desc.clone(), text.to_string());
         }
+    }
+
+    // TIT3 - Subtitle (energy level in Epidemic Sound)
+    fields.energy = tag
+        .get_string(&ItemKey::TrackSubtitle)
+        .map(|s| s.to_string());
 
-        // TBPM - Tempo
-        if let Some(item) = id3v2.get(&ItemKey::Bpm) {
-            if let Some(text) = item.value().text() {
-                bpm = text.parse::<u32>().ok();
+    // TBPM - Tempo
+    fields.bpm = tag.get_string(&ItemKey::Bpm).and_then(parse_bpm);
+}
+
+/// FLAC / OGG: mood and energy are stored as custom comments (MOOD=..., ENERGY=...)
+fn read_vorbis_fields(tag: &Tag, fields: &mut TagFields) {
+    fields.mood = find_text_by_key(tag, &["MOOD"]);
+    if fields.mood.is_some() {
+        fields.mood_source = Some("MOOD".to_string());
+    }
+    fields.energy = find_text_by_key(tag, &["ENERGY"]);
+    fields.bpm = find_text_by_key(tag, &["BPM", "TEMPO"]).and_then(|text| parse_bpm(&text));
+}
+
+/// M4A / MP4: mood and energy live in freeform (----) atoms, tempo in tmpo
+fn read_mp4_fields(tag: &Tag, fields: &mut TagFields) {
+    for key in ["----:com.apple.iTunes:MOOD", "----:com.apple.iTunes:VIBE"] {
+        if let Some(text) = find_text_by_key(tag, &[key]) {
+            fields.mood = Some(text);
+            fields.mood_source = Some(key.to_string());
+            break;
+        }
+    }
+    fields.energy = find_text_by_key(tag, &["----:com.apple.iTunes:ENERGY"]);
+    fields.bpm = find_text_by_key(tag, &["tmpo", "----:com.apple.iTunes:BPM"])
+        .and_then(|text| parse_bpm(&text));
+}
+
+/// Other formats (APE, RIFF INFO, ...): lofty's generic mood and BPM keys, plus an ENERGY item
+fn read_generic_fields(tag: &Tag, fields: &mut TagFields) {
+    fields.mood = tag.get_string(&ItemKey::Mood).map(|s| s.to_string());
+    if fields.mood.is_some() {
+        fields.mood_source = Some("Mood".to_string());
+    }
+    fields.energy = find_text_by_key(tag, &["ENERGY"]);
+    fields.bpm = tag.get_string(&ItemKey::Bpm).and_then(parse_bpm);
+}
+
+/// Write a single field ("genre", "mood" or "energy") to a file's tags
+/// Mood and energy go to the frames read_audio_metadata_full reads first, so the new value
+/// wins over older mood tags (TIT1/TIT3 for ID3v2, MOOD/ENERGY for Vorbis, freeform atoms for MP4)
+pub fn write_audio_metadata(path: &str, field: &str, value: &str) -> Result<(), SmelterError> {
+    let mut tagged_file = Probe::open(path)
+        .map_err(|e| SmelterError::Metadata(format!("Failed to open file: {}", e)))?
+        .read()
+        .map_err(|e| SmelterError::Metadata(format!("Failed to read file: {}", e)))?;
+
+    // Files without tags get a new tag of the format's preferred type
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file
+        .primary_tag_mut()
+        .ok_or_else(|| SmelterError::Metadata("File format doesn't support tags".to_string()))?;
+    let tag_type = tag.tag_type();
+
+    let key = match field {
+        "genre" => ItemKey::Genre,
+        "mood" => match tag_type {
+            TagType::Id3v2 => ItemKey::ContentGroup,
+            _ => ItemKey::Mood,
+        },
+        "energy" => match tag_type {
+            TagType::Id3v2 => ItemKey::TrackSubtitle,
+            TagType::Mp4Ilst => ItemKey::Unknown("----:com.apple.iTunes:ENERGY".to_string()),
+            _ => ItemKey::Unknown("ENERGY".to_string()),
+        },
+        _ => {
+            return Err(SmelterError::InvalidInput(format!(
+                "Unsupported field: {}",
+                field
+            )))
+        }
+    };
+
+    // insert_text refuses keys the tag format can't store; saving anyway would silently do nothing
+    if !tag.insert_text(key, value.to_string()) {
+        return Err(SmelterError::Metadata(format!(
+            "Writing {} is not supported for {:?} tags",
+            field, tag_type
+        )));
+    }
+
+    tagged_file
+        .save_to_path(path, WriteOptions::default())
+        .map_err(|e| SmelterError::Metadata(format!("Failed to write tags: {}", e)))
+}
+
+/// Largest embedded picture returned by read_cover_art (bigger art is skipped)
+const MAX_COVER_ART_BYTES: usize = 1024 * 1024;
+
+/// Read the first embedded picture as base64
+/// Returns None if the file has no art or the art is larger than MAX_COVER_ART_BYTES
+pub fn read_cover_art(path: &str) -> Result<Option<String>, SmelterError> {
+    let tagged_file = Probe::open(path)
+        .map_err(|e| SmelterError::Metadata(format!("Failed to open file: {}", e)))?
+        .read()
+        .map_err(|e| SmelterError::Metadata(format!("Failed to read file: {}", e)))?;
+
+    let picture = tagged_file
+        .tags()
+        .iter()
+        .find_map(|tag| tag.pictures().first());
+
+    Ok(picture
+        .filter(|p| p.data().len() <= MAX_COVER_ART_BYTES)
+        .map(|p| base64::engine::general_purpose::STANDARD.encode(p.data())))
+}
+
+/// Read every genre in a tag: all genre items, each split on ";" and "," (duplicates removed)
+fn read_genres(tag: &Tag) -> Vec<String> {
+    let mut genres: Vec<String> = Vec::new();
+    for value in tag.get_strings(&ItemKey::Genre) {
+        for part in value.split([';', ',']) {
+            let genre = normalize_genre(part).trim().to_string();
+            if !genre.is_empty() && !genres.contains(&genre) {
+                genres.push(genre);
             }
         }
-    } else if let Some(tag) = tagged_file.primary_tag() {
-        // Fallback to primary tag
-        title = tag.title().map(|s| s.to_string());
-        artist = tag.artist().map(|s| s.to_string());
-        genre = tag.genre().map(|s| s.to_string());
     }
+    genres
+}
 
-    Ok(AudioMetadata {
-        path: path.to_string(),
-        filename,
-        title,
-        artist,
-        genre,
-        mood,
-        energy,
-        bpm,
-        duration_secs: Some(duration_secs),
-        category_override: None,
+/// Map ID3v1 numeric genre codes to names: "17", "(17)" and "(17)Rock" all become "Rock"
+/// Textual genres (and unknown codes) are returned unchanged
+fn normalize_genre(raw: &str) -> String {
+    let trimmed = raw.trim();
+    let genre_name = |code: &str| {
+        code.parse::<usize>()
+            .ok()
+            .and_then(|n| lofty::id3::v1::GENRES.get(n))
+            .map(|name| name.to_string())
+    };
+
+    if let Some(rest) = trimmed.strip_prefix('(') {
+        if let Some((code, refinement)) = rest.split_once(')') {
+            if !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()) {
+                // "(17)Rock" - the text after the code is the more specific name
+                let refinement = refinement.trim();
+                if !refinement.is_empty() {
+                    return refinement.to_string();
+                }
+                if let Some(name) = genre_name(code) {
+                    return name;
+                }
+            }
+        }
+    } else if !trimmed.is_empty() && trimmed.chars().all(|c| c.is_ascii_digit()) {
+        if let Some(name) = genre_name(trimmed) {
+            return name;
+        }
+    }
+
+    raw.to_string()
+}
+
+/// Format a duration as "m:ss" (or "h:mm:ss" from an hour up), rounded to the nearest second
+pub fn format_duration(secs: f64) -> String {
+    let total = secs.max(0.0).round() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total % 3600 / 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Check whether a TKEY value is a musical key rather than a mood word
+/// Accepts standard notation ("C", "C#m", "Bb", "F# minor", "Ebmaj"), Camelot ("8A", "12B")
+/// and Open Key ("1d", "10m"); mood words like "Calm" or "Warm" are not keys
+fn is_musical_key(text: &str) -> bool {
+    let text = text.trim();
+
+    // Camelot / Open Key: a wheel position 1-12 and a letter
+    let digits_end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    if digits_end > 0 {
+        let position_ok = text[..digits_end].parse::<u32>().is_ok_and(|n| (1..=12).contains(&n));
+        let suffix = &text[digits_end..];
+        return position_ok && ["A", "B", "a", "b", "d", "m"].contains(&suffix);
+    }
+
+    // Standard notation: root note, optional accidental, optional mode
+    let mut chars = text.chars();
+    if !chars.next().is_some_and(|c| matches!(c.to_ascii_uppercase(), 'A'..='G')) {
+        return false;
+    }
+    let rest = chars.as_str();
+    let rest = rest
+        .strip_prefix(['#', 'b', '♯', '♭'])
+        .unwrap_or(rest)
+        .trim_start();
+    matches!(
+        rest.to_lowercase().as_str(),
+        "" | "m" | "min" | "minor" | "maj" | "major"
+    )
+}
+
+/// Parse a BPM value, accepting decimals ("128.00" -> 128) and ranges ("120-125" -> 120)
+/// Returns None for values that aren't numeric
+fn parse_bpm(text: &str) -> Option<u32> {
+    let first = text.trim().split('-').next()?.trim();
+    let bpm = first.parse::<f64>().ok()?;
+    if bpm.is_finite() && bpm > 0.0 {
+        Some(bpm.round() as u32)
+    } else {
+        None
+    }
+}
+
+/// Words used for energy levels, lowest to highest (matched case-insensitively)
+const ENERGY_LEVELS: [&[&str]; 5] = [
+    &["very low", "minimal", "calm", "ambient"],
+    &["low", "soft", "relaxed", "chill"],
+    &["medium", "moderate", "mid"],
+    &["high", "energetic", "upbeat", "driving"],
+    &["very high", "intense", "extreme"],
+];
+
+/// Convert a textual energy value ("High", "medium", "3") to a 1-5 level
+pub fn parse_energy_level(energy: &str) -> Option<u8> {
+    let energy = energy.trim().to_lowercase();
+
+    if let Ok(level) = energy.parse::<u8>() {
+        return Some(level).filter(|l| (1..=5).contains(l));
+    }
+
+    ENERGY_LEVELS
+        .iter()
+        .position(|words| words.contains(&energy.as_str()))
+        .map(|index| index as u8 + 1)
+}
+
+/// Read the release year from a tag's recording date or year item
+fn read_year(tag: &Tag) -> Option<u32> {
+    tag.get_string(&ItemKey::RecordingDate)
+        .and_then(parse_year)
+        .or_else(|| tag.get_string(&ItemKey::Year).and_then(parse_year))
+}
+
+/// Parse a year from a date string like "2021", "2021-06-14" or "2021-06-14T10:00:00"
+fn parse_year(text: &str) -> Option<u32> {
+    let digits: String = text.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() != 4 {
+        return None;
+    }
+    digits.parse::<u32>().ok().filter(|&y| y > 0)
+}
+
+/// Read the track number, accepting "3" as well as "3/12" forms
+fn read_track_number(tag: &Tag) -> Option<u32> {
+    tag.get_string(&ItemKey::TrackNumber)
+        .and_then(|text| text.split('/').next())
+        .and_then(|n| n.trim().parse::<u32>().ok())
+        .or_else(|| tag.track())
+}
+
+/// Find a text item by its raw key name (case-insensitive).
+/// Works for both mapped keys (e.g. MOOD -> ItemKey::Mood) and custom keys (e.g. ENERGY).
+fn find_text_by_key(tag: &Tag, names: &[&str]) -> Option<String> {
+    let tag_type = tag.tag_type();
+    tag.items().find_map(|item| {
+        let key = item.key().map_key(tag_type, true)?;
+        if names.iter().any(|name| key.eq_ignore_ascii_case(name)) {
+            item.value().text().map(|s| s.to_string())
+        } else {
+            None
+        }
     })
 }
 
+// Rules for reading a comment as a mood
+lazy_static::lazy_static! {
+    static ref MOOD_COMMENT_OPTIONS: Mutex<MoodCommentOptions> = Mutex::new(MoodCommentOptions::default());
+}
+
+/// Replace the rules for reading a comment as a mood (applies to files read from now on;
+/// cached files keep their mood until rescanned)
+pub fn set_mood_comment_options(options: MoodCommentOptions) {
+    *MOOD_COMMENT_OPTIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = options;
+}
+
+/// Check whether a comment should be used as the mood
+/// Deny keywords win over allow keywords, which win over the length and character rules
+fn comment_looks_like_mood(comment: &str, options: &MoodCommentOptions) -> bool {
+    let lower = comment.to_lowercase();
+    let mentions = |keywords: &[String]| {
+        keywords
+            .iter()
+            .filter(|k| !k.trim().is_empty())
+            .any(|k| lower.contains(&k.trim().to_lowercase()))
+    };
+
+    if mentions(&options.deny_keywords) {
+        return false;
+    }
+    if mentions(&options.allow_keywords) {
+        return true;
+    }
+    comment.len() < options.max_length && !comment.contains(|c| options.rejected_chars.contains(c))
+}
+
+/// Audio extensions recognized until set_audio_extensions is called
+pub const DEFAULT_AUDIO_EXTENSIONS: [&str; 10] = [
+    "mp3", "wav", "flac", "ogg", "oga", "m4a", "mp4", "aac", "aiff", "aif",
+];
+
+// Recognized audio extensions (lowercase, without the dot)
+lazy_static::lazy_static! {
+    static ref AUDIO_EXTENSIONS: Mutex<HashSet<String>> =
+        Mutex::new(normalize_extensions(&[]));
+}
+
+/// Lowercase extensions and strip a leading "."; an empty list means DEFAULT_AUDIO_EXTENSIONS
+fn normalize_extensions(extensions: &[String]) -> HashSet<String> {
+    let set: HashSet<String> = extensions
+        .iter()
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+    if set.is_empty() {
+        return DEFAULT_AUDIO_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect();
+    }
+    set
+}
+
+/// Replace the recognized audio extensions (case-insensitive, a leading "." is ignored)
+/// An empty list restores DEFAULT_AUDIO_EXTENSIONS; returns how many extensions are recognized
+pub fn set_audio_extensions(extensions: &[String]) -> u32 {
+    let set = normalize_extensions(extensions);
+    let count = set.len() as u32;
+    *lock_extensions() = set;
+    count
+}
+
+/// Lock the recognized extensions, recovering them if a thread panicked while holding the lock
+fn lock_extensions() -> MutexGuard<'static, HashSet<String>> {
+    AUDIO_EXTENSIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The currently recognized audio extensions
+pub fn audio_extensions() -> HashSet<String> {
+    lock_extensions().clone()
+}
+
+/// Check whether a path has a recognized audio file extension
+pub fn is_supported_audio_file(path: &Path) -> bool {
+    has_extension(path, &lock_extensions())
+}
+
+/// Check whether a path's extension (compared in lowercase) is one of `extensions`
+fn has_extension(path: &Path, extensions: &HashSet<String>) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.contains(&ext))
+}
+
+/// Build a matcher from filename glob patterns (None if there are no patterns)
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>, SmelterError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| {
+            SmelterError::InvalidInput(format!("Invalid pattern '{}': {}", pattern, e))
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| SmelterError::InvalidInput(format!("Invalid patterns: {}", e)))
+}
+
 /// Scan a directory for audio files and extract metadata
-pub fn scan_directory(dir_path: &str) -> Result<Vec<AudioMetadata>, String> {
+/// Metadata is read in parallel; results are sorted by path so the order is stable.
+/// Files filtered out by the include/exclude globs in `options` are skipped before reading.
+/// Files that can't be read are reported in `failures` instead of aborting the scan.
+/// Stops early when `cancel` is set, returning the files read so far
+pub fn scan_directory(
+    dir_path: &str,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+) -> Result<ScanReport, SmelterError> {
+    // Collect audio file paths first so metadata reads can run in parallel
+    let audio_paths = collect_audio_paths(dir_path, options, &audio_extensions(), cancel)?;
+    Ok(read_files_parallel(&audio_paths, cancel))
+}
+
+/// Walk a directory and collect the paths of files with one of `extensions` that pass the
+/// scan filters
+fn collect_audio_paths(
+    dir_path: &str,
+    options: &ScanOptions,
+    extensions: &HashSet<String>,
+    cancel: &CancelToken,
+) -> Result<Vec<String>, SmelterError> {
     use walkdir::WalkDir;
 
-    let mut results = Vec::new();
+    let include = build_glob_set(&options.include_globs)?;
+    let exclude = build_glob_set(&options.exclude_globs)?;
 
-    for entry in WalkDir::new(dir_path)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
+    let mut audio_paths = Vec::new();
 
-        // Check if it's an audio file
-        if let Some(ext) = path.extension() {
-            let ext_lower = ext.to_string_lossy().to_lowercase();
-            if ext_lower == "mp3" || ext_lower == "wav" {
-                if let Some(path_str) = path.to_str() {
-                    match read_audio_metadata_full(path_str) {
-                        Ok(metadata) => results.push(metadata),
-                        Err(e) => {
-                            eprintln!("Error reading {}: {}", path_str, e);
-                        }
+    let mut walker = WalkDir::new(dir_path).follow_links(options.follow_links);
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth);
+    }
+
+    // Canonical folders already walked, so a symlink back into the tree isn't scanned twice
+    let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+    // Prune ignored and hidden folders so their subtrees are never walked
+    // (the selected folder itself is always scanned)
+    let mut entries = walker.into_iter().filter_entry(|entry| {
+        if entry.depth() == 0 || !entry.file_type().is_dir() {
+            return true;
+        }
+        let name = entry.file_name().to_string_lossy();
+        let hidden = options.skip_hidden_dirs && name.starts_with('.');
+        let ignored = options
+            .ignored_dirs
+            .iter()
+            .any(|dir| dir.eq_ignore_ascii_case(&name));
+        !hidden && !ignored
+    });
+
+    while let Some(entry) = entries.next() {
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                // WalkDir reports symlinks pointing at an ancestor as loop errors
+                if e.loop_ancestor().is_some() {
+                    eprintln!("Skipping symlink loop: {}", e);
+                }
+                continue;
+            }
+        };
+
+        if entry.file_type().is_dir() {
+            if options.follow_links {
+                if let Ok(canonical) = std::fs::canonicalize(entry.path()) {
+                    if !visited_dirs.insert(canonical) {
+                        entries.skip_current_dir();
                     }
                 }
             }
+            continue;
+        }
+
+        let path = entry.path();
+
+        // Check the filename against the include/exclude patterns
+        let filename = entry.file_name();
+        if include.as_ref().is_some_and(|set| !set.is_match(filename))
+            || exclude.as_ref().is_some_and(|set| set.is_match(filename))
+        {
+            continue;
+        }
+
+        // Check if it's an audio file
+        if has_extension(path, extensions) {
+            if let Some(path_str) = path.to_str() {
+                audio_paths.push(path_str.to_string());
+            }
+        }
+    }
+
+    Ok(audio_paths)
+}
+
+/// Read metadata for many files in parallel, keeping the input order
+/// Entries are None for files skipped because `cancel` was set
+pub fn read_files_ordered(
+    paths: &[String],
+    cancel: &CancelToken,
+) -> Vec<Option<Result<AudioMetadata, SmelterError>>> {
+    use rayon::prelude::*;
+
+    paths
+        .par_iter()
+        .map(|path| {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            Some(read_audio_metadata_full(path))
+        })
+        .collect()
+}
+
+/// Read metadata for many files in parallel, splitting successes and failures
+fn read_files_parallel(audio_paths: &[String], cancel: &CancelToken) -> ScanReport {
+    use rayon::prelude::*;
+
+    let results: Vec<Result<AudioMetadata, (String, String)>> = audio_paths
+        .par_iter()
+        .filter_map(|path_str| {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            Some(read_audio_metadata_full(path_str).map_err(|e| {
+                eprintln!("Error reading {}: {}", path_str, e);
+                (path_str.clone(), e.to_string())
+            }))
+        })
+        .collect();
+
+    let mut report = ScanReport::default();
+    for result in results {
+        match result {
+            Ok(metadata) => report.files.push(metadata),
+            Err(failure) => report.failures.push(failure),
+        }
+    }
+
+    // Sort by path so the UI list is stable regardless of thread scheduling
+    report.files.sort_by(|a, b| a.path.cmp(&b.path));
+    report.failures.sort();
+
+    report
+}
+
+/// Re-scan a directory, only reading files that changed since they were cached
+/// Unchanged files are returned by path only, without loading their metadata;
+/// freshly read files are written back to the cache
+pub fn scan_directory_incremental(
+    dir_path: &str,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+) -> Result<IncrementalScanReport, SmelterError> {
+    let audio_paths = collect_audio_paths(dir_path, options, &audio_extensions(), cancel)?;
+
+    // A cheap mtime/size lookup decides which files need reading
+    let (unchanged, changed): (Vec<String>, Vec<String>) = audio_paths
+        .into_iter()
+        .partition(|path| cache::is_fresh(path).unwrap_or(false));
+
+    let report = read_files_parallel(&changed, cancel);
+    cache::cache_metadata_batch(&report.files)?;
+
+    Ok(IncrementalScanReport {
+        changed: report.files,
+        unchanged,
+        failures: report.failures,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Comments an Epidemic Sound FLAC or Ogg download carries
+    const COMMENTS: [(&str, &str); 6] = [
+        ("TITLE", "Anthem"),
+        ("ARTIST", "The Band"),
+        ("GENRE", "Rock"),
+        ("MOOD", "Energetic"),
+        ("ENERGY", "High"),
+        ("BPM", "128"),
+    ];
+
+    fn assert_comments_read(metadata: &AudioMetadata) {
+        assert_eq!(metadata.title.as_deref(), Some("Anthem"));
+        assert_eq!(metadata.artist.as_deref(), Some("The Band"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.mood.as_deref(), Some("Energetic"));
+        assert_eq!(metadata.energy.as_deref(), Some("High"));
+        assert_eq!(metadata.bpm, Some(128));
+        assert_eq!(metadata.duration_secs.map(f64::round), Some(1.0));
+    }
+
+    #[test]
+    fn flac_vorbis_comments_are_read() {
+        let dir = test_support::temp_dir("read_flac");
+        let path = dir.join("ES_Anthem.flac");
+        test_support::write_flac(&path, &COMMENTS);
+
+        assert_comments_read(&read_audio_metadata_full(path.to_str().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn ogg_vorbis_comments_are_read() {
+        let dir = test_support::temp_dir("read_ogg");
+        let path = dir.join("ES_Anthem.ogg");
+        test_support::write_ogg(&path, &COMMENTS);
+
+        assert_comments_read(&read_audio_metadata_full(path.to_str().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn scan_picks_up_flac_and_ogg() {
+        let dir = test_support::temp_dir("scan_flac_ogg");
+        test_support::write_flac(&dir.join("ES_One.flac"), &COMMENTS);
+        test_support::write_ogg(&dir.join("ES_Two.ogg"), &COMMENTS);
+        std::fs::write(dir.join("notes.txt"), b"not audio").unwrap();
+
+        let mut names: Vec<String> = scan_directory(
+            dir.to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::default(),
+        )
+        .unwrap()
+        .files
+        .into_iter()
+        .map(|metadata| metadata.filename)
+        .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["ES_One.flac".to_string(), "ES_Two.ogg".to_string()]
+        );
+    }
+
+    #[test]
+    fn m4a_freeform_atoms_are_read() {
+        let dir = test_support::temp_dir("read_m4a");
+        let path = dir.join("ES_Anthem.m4a");
+        test_support::write_m4a(
+            &path,
+            &[
+                (b"\xa9nam", "Anthem"),
+                (b"\xa9ART", "The Band"),
+                (b"\xa9gen", "Rock"),
+            ],
+            &[("MOOD", "Energetic"), ("ENERGY", "High")],
+        );
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Anthem"));
+        assert_eq!(metadata.artist.as_deref(), Some("The Band"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.mood.as_deref(), Some("Energetic"));
+        assert_eq!(metadata.energy.as_deref(), Some("High"));
+        assert_eq!(metadata.duration_secs.map(f64::round), Some(1.0));
+    }
+
+    #[test]
+    fn year_is_parsed_from_dates() {
+        let cases = [
+            ("2021", Some(2021)),
+            ("2021-06-14", Some(2021)),
+            ("2021-06-14T10:00:00", Some(2021)),
+            (" 1999 ", Some(1999)),
+            ("21", None),
+            ("June 2021", None),
+            ("", None),
+        ];
+
+        for (text, year) in cases {
+            assert_eq!(parse_year(text), year, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn vorbis_date_gives_the_year() {
+        let dir = test_support::temp_dir("read_year");
+        let path = dir.join("ES_Anthem.flac");
+        test_support::write_flac(&path, &[("TITLE", "Anthem"), ("DATE", "2021-06-14")]);
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+        assert_eq!(metadata.year, Some(2021));
+    }
+
+    #[test]
+    fn album_and_track_number_are_read() {
+        let dir = test_support::temp_dir("read_album");
+        let path = dir.join("ES_Theme.flac");
+        test_support::write_flac(
+            &path,
+            &[
+                ("TITLE", "Theme"),
+                ("ALBUM", "Score"),
+                ("TRACKNUMBER", "3/12"),
+            ],
+        );
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+        assert_eq!(metadata.album.as_deref(), Some("Score"));
+        assert_eq!(metadata.track_number, Some(3));
+    }
+
+    #[test]
+    fn parallel_scan_returns_files_sorted_by_path() {
+        let dir = test_support::temp_dir("scan_order");
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        for index in (0..24).rev() {
+            let folder = if index % 2 == 0 { "a" } else { "b" };
+            test_support::write_flac(
+                &dir.join(folder).join(format!("ES_{:02}.flac", index)),
+                &COMMENTS,
+            );
+        }
+
+        let paths: Vec<String> = scan_directory(
+            dir.to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::default(),
+        )
+        .unwrap()
+        .files
+        .into_iter()
+        .map(|metadata| metadata.path)
+        .collect();
+
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert_eq!(paths.len(), 24);
+        assert_eq!(paths, sorted);
+    }
+
+    #[test]
+    fn cover_art_is_read_only_when_embedded() {
+        let dir = test_support::temp_dir("cover_art");
+        let with_art = dir.join("ES_Art.flac");
+        let without_art = dir.join("ES_Plain.flac");
+        test_support::write_flac_with_art(&with_art, &COMMENTS, b"\x89PNG fake image");
+        test_support::write_flac(&without_art, &COMMENTS);
+
+        let art = read_cover_art(with_art.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD
+                .decode(art)
+                .unwrap(),
+            b"\x89PNG fake image"
+        );
+        assert_eq!(read_cover_art(without_art.to_str().unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn wav_audio_properties_are_read() {
+        let dir = test_support::temp_dir("wav_properties");
+        let path = dir.join("ES_Hit.wav");
+        test_support::write_wav(&path, 48_000, 2, 1);
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(metadata.sample_rate, Some(48_000));
+        assert_eq!(metadata.bit_depth, Some(16));
+        assert_eq!(metadata.channels, Some(2));
+        assert_eq!(metadata.bitrate_kbps, Some(1536));
+    }
+
+    #[test]
+    fn numeric_genre_codes_become_names() {
+        let cases = [
+            ("17", "Rock"),
+            ("(0)", "Blues"),
+            ("(32)", "Classical"),
+            ("(17)Hard Rock", "Hard Rock"),
+            ("Lo-Fi", "Lo-Fi"),
+            ("(999)", "(999)"),
+        ];
+
+        for (raw, expected) in cases {
+            assert_eq!(normalize_genre(raw), expected, "genre {:?}", raw);
+        }
+    }
+
+    #[test]
+    fn energy_words_map_to_levels_case_insensitively() {
+        for energy in ["high", "HIGH", "Energetic", " upbeat ", "4"] {
+            assert_eq!(parse_energy_level(energy), Some(4), "energy {:?}", energy);
+        }
+        assert_eq!(parse_energy_level("Very Low"), Some(1));
+        assert_eq!(parse_energy_level("medium"), Some(3));
+        assert_eq!(parse_energy_level("7"), None);
+        assert_eq!(parse_energy_level("spicy"), None);
+    }
+
+    #[test]
+    fn scan_keeps_only_files_matching_the_include_globs() {
+        let dir = test_support::temp_dir("scan_globs");
+        for name in [
+            "Drums_master.wav",
+            "Drums_stem.wav",
+            "Bass_master.wav",
+            "Bass_bounce.wav",
+        ] {
+            test_support::write_wav(&dir.join(name), 8000, 1, 1);
+        }
+        let options = ScanOptions {
+            include_globs: vec!["*_master.*".to_string()],
+            exclude_globs: vec!["Bass*".to_string()],
+        };
+
+        let names: Vec<String> =
+            scan_directory(dir.to_str().unwrap(), &options, &CancelToken::default())
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|metadata| metadata.filename)
+                .collect();
+
+        assert_eq!(names, vec!["Drums_master.wav".to_string()]);
+    }
+
+    #[test]
+    fn invalid_glob_is_an_error() {
+        let dir = test_support::temp_dir("scan_bad_glob");
+        let options = ScanOptions {
+            include_globs: vec!["[".to_string()],
+            ..Default::default()
+        };
+
+        assert!(scan_directory(dir.to_str().unwrap(), &options, &CancelToken::default()).is_err());
+    }
+
+    #[test]
+    fn scan_stops_at_the_max_depth() {
+        let dir = test_support::temp_dir("scan_depth");
+        std::fs::create_dir_all(dir.join("sub/deep")).unwrap();
+        for name in ["top.wav", "sub/middle.wav", "sub/deep/bottom.wav"] {
+            test_support::write_wav(&dir.join(name), 8000, 1, 1);
+        }
+        let names_at_depth = |max_depth| -> Vec<String> {
+            let options = ScanOptions {
+                max_depth: Some(max_depth),
+                ..Default::default()
+            };
+            scan_directory(dir.to_str().unwrap(), &options, &CancelToken::default())
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|metadata| metadata.filename)
+                .collect()
+        };
+
+        assert_eq!(names_at_depth(1), vec!["top.wav".to_string()]);
+        let mut two_levels = names_at_depth(2);
+        two_levels.sort();
+        assert_eq!(
+            two_levels,
+            vec!["middle.wav".to_string(), "top.wav".to_string()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_following_links_survives_symlink_loops() {
+        let dir = test_support::temp_dir("scan_symlink_loop");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        test_support::write_wav(&dir.join("top.wav"), 8000, 1, 1);
+        test_support::write_wav(&dir.join("sub/inner.wav"), 8000, 1, 1);
+        // One link back to the root (a loop) and one alias of a folder that is already walked
+        std::os::unix::fs::symlink(&dir, dir.join("sub/back_to_root")).unwrap();
+        std::os::unix::fs::symlink(dir.join("sub"), dir.join("alias")).unwrap();
+        let options = ScanOptions {
+            follow_links: true,
+            ..Default::default()
+        };
+
+        let mut names: Vec<String> =
+            scan_directory(dir.to_str().unwrap(), &options, &CancelToken::default())
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|metadata| metadata.filename)
+                .collect();
+        names.sort();
+
+        assert_eq!(names, vec!["inner.wav".to_string(), "top.wav".to_string()]);
+    }
+
+    #[test]
+    fn unreadable_files_are_reported_as_failures() {
+        let dir = test_support::temp_dir("scan_failures");
+        test_support::write_wav(&dir.join("good.wav"), 8000, 1, 1);
+        let broken = test_support::write_file(&dir, "broken.wav", b"definitely not a wav");
+
+        let report = scan_directory(
+            dir.to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::default(),
+        )
+        .unwrap();
+
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].filename, "good.wav");
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, broken);
+        assert!(!report.failures[0].1.is_empty());
+    }
+
+    #[test]
+    fn incremental_rescan_only_rereads_changed_files() {
+        let _databases = test_support::use_test_databases();
+        cache::init_database().unwrap();
+        let dir = test_support::temp_dir("scan_incremental");
+        let kept = dir.join("kept.wav");
+        let touched = dir.join("touched.wav");
+        test_support::write_wav(&kept, 8000, 1, 1);
+        test_support::write_wav(&touched, 8000, 1, 1);
+        let scan = || {
+            scan_directory_incremental(
+                dir.to_str().unwrap(),
+                &ScanOptions::default(),
+                &CancelToken::default(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(scan().changed.len(), 2);
+
+        std::fs::File::options()
+            .write(true)
+            .open(&touched)
+            .unwrap()
+            .set_modified(
+                std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000),
+            )
+            .unwrap();
+        let rescan = scan();
+
+        let changed: Vec<&str> = rescan.changed.iter().map(|m| m.filename.as_str()).collect();
+        assert_eq!(changed, vec!["touched.wav"]);
+        assert_eq!(rescan.unchanged, vec![kept.to_string_lossy().to_string()]);
+    }
+
+    #[test]
+    fn ignored_and_hidden_folders_are_pruned() {
+        let dir = test_support::temp_dir("scan_ignored_dirs");
+        for folder in ["Drums", "Samples", ".Trash", "__MACOSX"] {
+            std::fs::create_dir_all(dir.join(folder)).unwrap();
+            test_support::write_wav(&dir.join(folder).join("kick.wav"), 8000, 1, 1);
+        }
+        let options = ScanOptions {
+            ignored_dirs: vec!["samples".to_string(), "__MACOSX".to_string()],
+            ..Default::default()
+        };
+
+        let paths: Vec<String> =
+            scan_directory(dir.to_str().unwrap(), &options, &CancelToken::default())
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|metadata| metadata.path)
+                .collect();
+
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].contains("Drums"));
+    }
+
+    #[test]
+    fn bpm_accepts_decimals_and_ranges() {
+        let cases = [
+            ("128", Some(128)),
+            ("128.00", Some(128)),
+            ("127.6", Some(128)),
+            ("120-125", Some(120)),
+            (" 90 ", Some(90)),
+            ("abc", None),
+            ("0", None),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse_bpm(text), expected, "bpm {:?}", text);
+        }
+    }
+
+    #[test]
+    fn every_genre_is_listed() {
+        let dir = test_support::temp_dir("read_genres");
+        let path = dir.join("ES_Mixed.flac");
+        test_support::write_flac(
+            &path,
+            &[("GENRE", "Rock; Alternative"), ("GENRE", "Indie, Rock")],
+        );
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(metadata.genres, vec!["Rock", "Alternative", "Indie"]);
+        assert_eq!(metadata.genre.as_deref(), Some("Rock; Alternative"));
+    }
+
+    #[test]
+    fn wav_duration_matches_a_default_parse() {
+        let dir = test_support::temp_dir("wav_duration");
+        let path = dir.join("tone.wav");
+        test_support::write_wav(&path, 44_100, 2, 3);
+        let path = path.to_str().unwrap();
+
+        let default_duration = Probe::open(path)
+            .unwrap()
+            .read()
+            .unwrap()
+            .properties()
+            .duration();
+        let metadata = read_audio_metadata_full(path).unwrap();
+
+        assert_eq!(metadata.duration_secs, Some(default_duration.as_secs_f64()));
+        assert!((metadata.duration_secs.unwrap() - 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn flac_mood_and_energy_come_from_vorbis_comments() {
+        let dir = test_support::temp_dir("flac_fields");
+        let path = dir.join("pad.flac");
+        test_support::write_flac(
+            &path,
+            &[
+                ("TITLE", "Night Pad"),
+                ("GENRE", "Ambient"),
+                ("MOOD", "Calm"),
+                ("ENERGY", "Low"),
+                ("BPM", "90"),
+                ("ALBUM", "Textures"),
+                ("DATE", "2021-06-14"),
+                ("TRACKNUMBER", "3/12"),
+            ],
+        );
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Night Pad"));
+        assert_eq!(metadata.genre.as_deref(), Some("Ambient"));
+        assert_eq!(metadata.mood.as_deref(), Some("Calm"));
+        assert_eq!(metadata.mood_source.as_deref(), Some("MOOD"));
+        assert_eq!(metadata.energy.as_deref(), Some("Low"));
+        assert_eq!(metadata.bpm, Some(90));
+        assert_eq!(metadata.album.as_deref(), Some("Textures"));
+        assert_eq!(metadata.year, Some(2021));
+        assert_eq!(metadata.track_number, Some(3));
+    }
+
+    /// Compares parse times on a 1 GB WAV; run with
+    /// `cargo test bench_large_wav_parse -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_large_wav_parse() {
+        const ROUNDS: u32 = 50;
+        let dir = test_support::temp_dir("wav_bench");
+        let path = dir.join("long.wav");
+        // 48 kHz stereo for ~93 minutes is just over 1 GB of samples
+        test_support::write_wav(&path, 48_000, 2, 5_600);
+        let path = path.to_str().unwrap();
+
+        let start = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            Probe::open(path).unwrap().read().unwrap();
+        }
+        let default_time = start.elapsed() / ROUNDS;
+
+        let start = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            open_for_metadata(path).unwrap().read().unwrap();
+        }
+        let metadata_time = start.elapsed() / ROUNDS;
+
+        assert!(
+            metadata_time <= default_time,
+            "metadata parse took {:?}, default parse {:?}",
+            metadata_time,
+            default_time
+        );
+    }
+
+    #[test]
+    fn empty_files_are_flagged_as_corrupt() {
+        let dir = test_support::temp_dir("read_empty");
+        let path = test_support::write_file(&dir, "ES_Stub.mp3", b"");
+
+        let err = read_audio_metadata_full(&path).unwrap_err();
+
+        assert!(matches!(err, SmelterError::Corrupt(_)), "{:?}", err);
+        assert!(err.to_string().contains("empty"), "{}", err);
+    }
+
+    #[test]
+    fn unrecognized_formats_are_flagged_as_corrupt() {
+        let dir = test_support::temp_dir("read_unknown");
+        let path = test_support::write_file(&dir, "ES_Notes.bin", b"definitely not audio at all");
+
+        let err = read_audio_metadata_full(&path).unwrap_err();
+
+        assert!(matches!(err, SmelterError::Corrupt(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn empty_files_end_up_in_the_scan_failures() {
+        let dir = test_support::temp_dir("scan_empty");
+        test_support::write_wav(&dir.join("good.wav"), 8000, 1, 1);
+        let stub = test_support::write_file(&dir, "stub.wav", b"");
+
+        let report = scan_directory(
+            dir.to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::default(),
+        )
+        .unwrap();
+
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, stub);
+        assert!(report.failures[0].1.contains("empty"));
+    }
+
+    #[test]
+    fn extensions_are_lowercased_without_the_dot() {
+        let set =
+            normalize_extensions(&[".AIFF".to_string(), " wav ".to_string(), ".".to_string()]);
+
+        let mut extensions: Vec<String> = set.into_iter().collect();
+        extensions.sort();
+        assert_eq!(extensions, ["aiff", "wav"]);
+        assert_eq!(
+            normalize_extensions(&[]).len(),
+            DEFAULT_AUDIO_EXTENSIONS.len()
+        );
+    }
+
+    #[test]
+    fn only_configured_extensions_are_scanned() {
+        let dir = test_support::temp_dir("extensions");
+        for name in ["kick.wav", "pad.aiff", "LOUD.AIFF", "hit.mp3"] {
+            test_support::write_file(&dir, name, b"");
+        }
+
+        let found = collect_audio_paths(
+            dir.to_str().unwrap(),
+            &ScanOptions::default(),
+            &normalize_extensions(&["aiff".to_string()]),
+            &CancelToken::default(),
+        )
+        .unwrap();
+
+        let mut names: Vec<String> = found
+            .iter()
+            .map(|path| {
+                Path::new(path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, ["LOUD.AIFF", "pad.aiff"]);
+    }
+
+    /// A short WAV carrying an ID3v2 tag with one `key` frame
+    fn wav_with_id3_frame(dir: &Path, name: &str, key: ItemKey, value: &str) -> String {
+        let path = dir.join(name);
+        test_support::write_wav(&path, 8000, 1, 1);
+        let mut tag = Tag::new(TagType::Id3v2);
+        assert!(tag.insert_text(key, value.to_string()));
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn mood_source_names_the_id3_frame_it_came_from() {
+        let dir = test_support::temp_dir("mood_source_id3");
+        let cases = [
+            (ItemKey::ContentGroup, "Happy", "ContentGroup"),
+            (ItemKey::Mood, "Calm", "Mood"),
+            (ItemKey::Comment, "Dreamy", "Comment"),
+            (ItemKey::InitialKey, "Dark", "InitialKey"),
+        ];
+
+        for (key, mood, source) in cases {
+            let path = wav_with_id3_frame(&dir, &format!("{}.wav", source), key, mood);
+
+            let metadata = read_audio_metadata_full(&path).unwrap();
+
+            assert_eq!(metadata.mood.as_deref(), Some(mood), "{}", source);
+            assert_eq!(metadata.mood_source.as_deref(), Some(source));
         }
     }
 
-    Ok(results)
+    #[test]
+    fn mood_source_names_the_txxx_description() {
+        let dir = test_support::temp_dir("mood_source_txxx");
+        let path = dir.join("ES_Vibe.wav");
+        test_support::write_wav(&path, 8000, 1, 1);
+        let mut tag = lofty::id3::v2::Id3v2Tag::default();
+        tag.insert_user_text("vibe".to_string(), "Chill".to_string());
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(metadata.mood.as_deref(), Some("Chill"));
+        assert_eq!(metadata.mood_source.as_deref(), Some("TXXX:vibe"));
+    }
+
+    #[test]
+    fn mood_source_is_set_for_vorbis_comments_only_when_there_is_a_mood() {
+        let dir = test_support::temp_dir("mood_source_vorbis");
+        let tagged = dir.join("ES_Tagged.flac");
+        let untagged = dir.join("ES_Untagged.flac");
+        test_support::write_flac(&tagged, &COMMENTS);
+        test_support::write_flac(&untagged, &[("TITLE", "Plain")]);
+
+        let tagged = read_audio_metadata_full(tagged.to_str().unwrap()).unwrap();
+        let untagged = read_audio_metadata_full(untagged.to_str().unwrap()).unwrap();
+
+        assert_eq!(tagged.mood_source.as_deref(), Some("MOOD"));
+        assert_eq!(untagged.mood_source, None);
+    }
+
+    #[test]
+    fn musical_keys_are_not_moods() {
+        let cases = [
+            ("Calm", false),
+            ("Energetic", false),
+            ("Dark", false),
+            ("C#m", true),
+            ("Bb", true),
+            ("F# minor", true),
+            ("8A", true),
+            ("12B", true),
+            ("13A", false),
+        ];
+
+        for (text, is_key) in cases {
+            assert_eq!(is_musical_key(text), is_key, "key {:?}", text);
+        }
+    }
+
+    #[test]
+    fn a_longer_comment_is_a_mood_with_a_raised_limit() {
+        let comment = "Warm, nostalgic and slowly building towards a hopeful ending";
+        assert_eq!(comment.len(), 60);
+        let raised = MoodCommentOptions {
+            max_length: 80,
+            ..Default::default()
+        };
+
+        assert!(!comment_looks_like_mood(
+            comment,
+            &MoodCommentOptions::default()
+        ));
+        assert!(comment_looks_like_mood(comment, &raised));
+    }
+
+    #[test]
+    fn mood_comment_keywords_override_the_length_and_character_rules() {
+        let options = MoodCommentOptions {
+            allow_keywords: vec!["mood:".to_string()],
+            deny_keywords: vec!["www".to_string()],
+            ..Default::default()
+        };
+
+        assert!(comment_looks_like_mood("Mood: Calm. Reflective.", &options));
+        assert!(!comment_looks_like_mood("www.example.com", &options));
+        assert!(!comment_looks_like_mood(
+            "Mood: see www.example.com",
+            &options
+        ));
+        assert!(comment_looks_like_mood("Uplifting", &options));
+        assert!(!comment_looks_like_mood(
+            "Uplifting.",
+            &MoodCommentOptions::default()
+        ));
+    }
+
+    #[test]
+    fn ordered_read_keeps_the_input_order() {
+        let dir = test_support::temp_dir("read_ordered");
+        let paths: Vec<String> = (0..24)
+            .rev()
+            .map(|index| {
+                let path = dir.join(format!("ES_{:02}.flac", index));
+                test_support::write_flac(&path, &COMMENTS);
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+
+        let read: Vec<String> = read_files_ordered(&paths, &CancelToken::default())
+            .into_iter()
+            .map(|result| result.unwrap().unwrap().path)
+            .collect();
+
+        assert_eq!(read, paths);
+    }
+
+    #[test]
+    fn cancelled_ordered_read_skips_every_file() {
+        let dir = test_support::temp_dir("read_ordered_cancel");
+        let path = dir.join("ES_Skipped.flac");
+        test_support::write_flac(&path, &COMMENTS);
+        let cancel = CancelToken::default();
+        cancel.cancel();
+
+        let read = read_files_ordered(&[path.to_str().unwrap().to_string()], &cancel);
+
+        assert_eq!(read.len(), 1);
+        assert!(read[0].is_none());
+    }
+
+    #[test]
+    fn durations_format_as_minutes_or_hours() {
+        let cases = [
+            (5.0, "0:05"),
+            (65.0, "1:05"),
+            (3725.0, "1:02:05"),
+            (59.6, "1:00"),
+        ];
+
+        for (secs, expected) in cases {
+            assert_eq!(format_duration(secs), expected, "{} seconds", secs);
+        }
+    }
+
+    #[test]
+    fn txxx_frames_are_read_into_custom_fields() {
+        let dir = test_support::temp_dir("custom_fields");
+        let path = dir.join("ES_Project.wav");
+        test_support::write_wav(&path, 8000, 1, 1);
+        let mut tag = lofty::id3::v2::Id3v2Tag::default();
+        tag.insert_user_text("Project".to_string(), "AlphaCut".to_string());
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            metadata.custom_fields.get("Project").map(String::as_str),
+            Some("AlphaCut")
+        );
+    }
+
+    #[test]
+    fn aiff_id3_tags_are_read() {
+        let dir = test_support::temp_dir("read_aiff");
+        let path = dir.join("ES_Stem.aiff");
+        test_support::write_aiff(&path, 48_000, 2, 2);
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title("Stem".to_string());
+        tag.set_artist("Composer".to_string());
+        tag.set_genre("Cinematic".to_string());
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+        let metadata = read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Stem"));
+        assert_eq!(metadata.artist.as_deref(), Some("Composer"));
+        assert_eq!(metadata.genre.as_deref(), Some("Cinematic"));
+        assert_eq!(metadata.sample_rate, Some(48_000));
+        assert!((metadata.duration_secs.unwrap() - 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn aiff_and_aif_files_are_scanned() {
+        let dir = test_support::temp_dir("scan_aiff");
+        test_support::write_aiff(&dir.join("ES_One.aiff"), 44_100, 1, 1);
+        test_support::write_aiff(&dir.join("ES_Two.AIF"), 44_100, 1, 1);
+
+        let report = scan_directory(
+            dir.to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::default(),
+        )
+        .unwrap();
+
+        let names: Vec<&str> = report.files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, ["ES_One.aiff", "ES_Two.AIF"]);
+        assert!(report.failures.is_empty());
+    }
 }
@@ -1,27 +1,238 @@
 // The Smelter - Music file organization module
 pub mod cache;
+pub mod cancel;
+pub mod error;
+pub mod fingerprint;
+pub mod genre_map;
+pub mod hash;
 pub mod metadata;
 pub mod organize;
+pub mod undo;
+pub mod watch;
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub use error::SmelterError;
 
 /// Audio file metadata extracted from ID3 tags
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AudioMetadata {
     pub path: String,
     pub filename: String,
+    /// Whether organizing with the same `sfx_prefix` sends the file to the SFX folder
+    /// Set by the scan commands from their `sfx_prefix` argument; derived from the filename,
+    /// never cached
+    #[serde(default)]
+    pub is_sfx: bool,
     pub title: Option<String>,
     pub artist: Option<String>,
     pub genre: Option<String>,
+    /// Every genre the file lists (from all genre frames, split on ";" and ","); `genre` stays as-is
+    #[serde(default)]
+    pub genres: Vec<String>,
     pub mood: Option<String>,
+    /// Where `mood` was read from (e.g. "ContentGroup", "Comment", "TXXX:vibe"); only set on
+    /// fresh reads, never cached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mood_source: Option<String>,
     pub energy: Option<String>,
+    /// Energy on a 1 (lowest) to 5 (highest) scale, derived from `energy`
+    pub energy_level: Option<u8>,
     pub bpm: Option<u32>,
     pub duration_secs: Option<f64>,
+    /// `duration_secs` formatted for display ("m:ss", or "h:mm:ss" from an hour up)
+    #[serde(default)]
+    pub duration_display: Option<String>,
+    /// Release year (parsed from TDRC/Year or Vorbis DATE)
+    pub year: Option<u32>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    /// Filesystem modification time in Unix seconds (when the file was added or last changed)
+    #[serde(default)]
+    pub file_modified: Option<i64>,
+    /// Audio properties (e.g. 44100 Hz / 16-bit / 2 channels)
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    pub bitrate_kbps: Option<u32>,
+    /// Custom text frames by description (ID3v2 TXXX, e.g. "Project" -> "AlphaCut")
+    #[serde(default)]
+    pub custom_fields: HashMap<String, String>,
+    /// Base64-encoded embedded cover art (only filled when requested, never cached)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_art: Option<String>,
     /// Optional per-file category override (frontend sets this when user selects a specific field)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub category_override: Option<String>,
 }
 
+/// Options controlling which files a directory scan picks up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Only scan files whose name matches one of these globs (e.g. "*_master.*"); empty scans all
+    pub include_globs: Vec<String>,
+    /// Skip files whose name matches any of these globs
+    pub exclude_globs: Vec<String>,
+    /// How many folder levels to descend (1 = only the selected folder; None = unlimited)
+    pub max_depth: Option<usize>,
+    /// Follow symbolic links into other folders (off by default: links can form loops)
+    pub follow_links: bool,
+    /// Folder names that are never scanned (case-insensitive, e.g. "Samples")
+    pub ignored_dirs: Vec<String>,
+    /// Skip folders whose name starts with "." (e.g. ".Trash")
+    pub skip_hidden_dirs: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            max_depth: None,
+            follow_links: false,
+            // macOS zip leftovers: "._" resource files that look like audio but aren't
+            ignored_dirs: vec!["__MACOSX".to_string()],
+            skip_hidden_dirs: true,
+        }
+    }
+}
+
+/// Rules for using a file's comment as its mood (when no mood frame is set)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MoodCommentOptions {
+    /// Comments this long (in bytes) or longer are not moods
+    pub max_length: usize,
+    /// Comments containing any of these characters are not moods (e.g. "." marks a sentence)
+    pub rejected_chars: String,
+    /// Comments mentioning any of these words are always moods (case-insensitive)
+    pub allow_keywords: Vec<String>,
+    /// Comments mentioning any of these words are never moods (e.g. "www", "copyright")
+    pub deny_keywords: Vec<String>,
+}
+
+impl Default for MoodCommentOptions {
+    fn default() -> Self {
+        Self {
+            max_length: 50,
+            rejected_chars: ".".to_string(),
+            allow_keywords: Vec::new(),
+            deny_keywords: Vec::new(),
+        }
+    }
+}
+
+/// Result of scanning a directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanReport {
+    /// Metadata for every file that was read successfully, sorted by path
+    pub files: Vec<AudioMetadata>,
+    /// (path, error) for every audio file that couldn't be read
+    pub failures: Vec<(String, String)>,
+}
+
+/// Result of an incremental directory re-scan
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncrementalScanReport {
+    /// Metadata for files that were new or changed since they were cached (re-read from disk)
+    pub changed: Vec<AudioMetadata>,
+    /// Paths of files whose cache entry is still valid (not re-read)
+    pub unchanged: Vec<String>,
+    /// (path, error) for every changed file that couldn't be read
+    pub failures: Vec<(String, String)>,
+}
+
+/// How to handle a destination file that already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    /// Keep both files by adding a numbered suffix (e.g. `Track_1.mp3`)
+    #[default]
+    Rename,
+    /// Leave the existing file alone and don't touch the source
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Skip files whose exact contents are already in the category folder, rename the rest
+    Merge,
+}
+
+/// Options controlling how files are organized
+/// Shared by organize, preview and duplicate checks so they always agree on categories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrganizeOptions {
+    /// Field to organize by ("genre", "mood", "energy", "artist", "year", "album", "bpm", "duration",
+    /// "date_added" for YYYY-MM folders by file date, "txxx:<name>" for a custom TXXX frame,
+    /// "alpha" for A-Z/# folders by the artist's first letter, or "alpha:title"/"alpha:filename"),
+    /// or nested levels like "genre/mood"
+    /// "flatten" puts every file directly in the output folder, ignoring categories
+    pub organize_by: String,
+    /// "move", "copy", "hardlink" or "symlink"
+    pub operation: String,
+    /// Filename prefix that marks music files; anything else is SFX (empty disables SFX detection)
+    pub sfx_prefix: String,
+    /// What to do when a file with the same name already exists at the destination
+    pub conflict_strategy: ConflictStrategy,
+    /// Move files back to their original location if any file fails ("move" only)
+    pub atomic: bool,
+    /// Rename files using a template like "{artist} - {title}" (None keeps original names)
+    pub filename_template: Option<String>,
+    /// Longest allowed destination filename in bytes; longer names are shortened
+    pub max_filename_bytes: usize,
+    /// Treat names differing only in case as the same file (default on macOS and Windows,
+    /// whose filesystems are case-insensitive)
+    pub case_insensitive_names: bool,
+    /// Compute destinations (including collision suffixes) without touching any files
+    pub dry_run: bool,
+    /// Hash source and copy after copying and count mismatches as errors ("copy" only, slower)
+    pub verify_copies: bool,
+    /// How many times to retry a move/copy that failed with a transient error (e.g. a file
+    /// briefly locked on a network drive); 0 disables retries
+    pub retry_attempts: u32,
+    /// Write manifest.csv (source, destination, category, operation, status per file) to the
+    /// output folder after organizing; not written in dry-run mode
+    pub write_manifest: bool,
+    /// Keep each file's subfolders below this folder under its category
+    /// (`root/2023/x.mp3` -> `output/Rock/2023/x.mp3`); files outside it are placed flat
+    pub preserve_structure_root: Option<String>,
+    /// Copy files into every category of a multi-value field (e.g. each mood); "copy" only
+    pub split_multivalue: bool,
+    /// Skip a file when its destination already holds an identical copy, so re-running an
+    /// organize into the same folder doesn't create `_1` copies of files already placed
+    pub skip_identical: bool,
+    /// Detect duplicates by content hash instead of by filename
+    pub compare_content: bool,
+    /// Detect duplicates by acoustic fingerprint, catching re-encodes (CPU-heavy, overrides compare_content)
+    pub compare_fingerprint: bool,
+}
+
+impl Default for OrganizeOptions {
+    fn default() -> Self {
+        Self {
+            organize_by: "genre".to_string(),
+            operation: "move".to_string(),
+            sfx_prefix: organize::DEFAULT_SFX_PREFIX.to_string(),
+            conflict_strategy: ConflictStrategy::default(),
+            atomic: false,
+            filename_template: None,
+            max_filename_bytes: organize::DEFAULT_MAX_FILENAME_BYTES,
+            case_insensitive_names: cfg!(any(target_os = "macos", target_os = "windows")),
+            dry_run: false,
+            verify_copies: false,
+            retry_attempts: organize::DEFAULT_RETRY_ATTEMPTS,
+            write_manifest: false,
+            preserve_structure_root: None,
+            split_multivalue: false,
+            skip_identical: true,
+            compare_content: false,
+            compare_fingerprint: false,
+        }
+    }
+}
+
 /// Result of organizing files
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrganizeResult {
@@ -29,6 +240,104 @@ pub struct OrganizeResult {
     pub error_count: u32,
     pub skipped_count: u32,
     pub errors: Vec<String>,
+    /// True if the operation was cancelled before all files were processed
+    pub cancelled: bool,
+    /// True if organizing stopped early because the output folder disappeared
+    /// (e.g. an external drive was disconnected); the counts cover the files handled before that
+    #[serde(default)]
+    pub destination_unavailable: bool,
+    /// Computed destinations for each file (only filled in dry-run mode)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub planned: Vec<PlannedMove>,
+    /// (source_path, destination_path) for every file that was successfully organized
+    pub moved: Vec<(String, String)>,
+}
+
+/// Where a file would go when organized (dry-run result)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedMove {
+    pub source_path: String,
+    pub destination_path: String,
+    pub category: String,
+}
+
+/// Why a file was assigned to its category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CategoryReason {
+    /// The filename lacks the music prefix, so the file is treated as SFX
+    SfxPrefix,
+    /// The user picked the category for this file
+    Override,
+    /// Taken from the genre tag
+    GenreTag,
+    /// Taken from the mood tag
+    MoodTag,
+    /// Taken from another tag (artist, year, BPM, ...)
+    OtherTag,
+    /// A tag needed for the category is missing, so the file goes to an "Unknown" folder
+    FallbackUnknown,
+    /// Flatten mode: every file goes directly into the output folder
+    Flattened,
+}
+
+/// A file in the organization preview
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewEntry {
+    pub filename: String,
+    pub reason: CategoryReason,
+    /// Name the file will get in the output folder, after templating and collision suffixes
+    /// (e.g. `Drums_1.wav`); only filled when the preview is given an output folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_filename: Option<String>,
+    /// The destination already holds this file, so organizing will skip it
+    /// (`final_filename` is then the name of the file already there)
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+/// Disk space needed to organize files versus what the destination has free
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceCheck {
+    pub required_bytes: u64,
+    pub available_bytes: u64,
+    pub sufficient: bool,
+}
+
+/// Progress update emitted while organizing files or checking for duplicates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizeProgress {
+    pub done: u32,
+    pub total: u32,
+    /// File currently being processed (None once everything is finished)
+    pub current_filename: Option<String>,
+}
+
+/// Statistics about the metadata cache (for the settings screen)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub entry_count: u32,
+    pub db_file_bytes: u64,
+    /// Unix timestamps of the oldest and newest cached entries
+    pub oldest_entry: Option<i64>,
+    pub newest_entry: Option<i64>,
+}
+
+/// Whether a file's cache entry still matches the file on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FreshnessStatus {
+    /// Cached with the file's current mtime and size
+    Fresh,
+    /// Changed since it was cached, or not cached at all
+    Stale,
+    /// The file no longer exists
+    Missing,
+}
+
+/// Cache freshness of one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheFreshness {
+    pub path: String,
+    pub status: FreshnessStatus,
 }
 
 /// Information about a duplicate file
@@ -38,6 +347,18 @@ pub struct DuplicateInfo {
     pub source_filename: String,
     pub existing_path: String,
     pub category: String,
+    pub match_type: DuplicateMatchType,
+}
+
+/// How a duplicate was detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateMatchType {
+    /// Same filename in the target folder
+    Name,
+    /// Same file contents (possibly under a different name)
+    Content,
+    /// Same audio by acoustic fingerprint (e.g. a re-encode at a different bitrate)
+    Fingerprint,
 }
 
 /// A group of source files with the same filename going to the same category
@@ -46,6 +367,19 @@ pub struct SourceDuplicateGroup {
     pub filename: String,
     pub category: String,
     pub files: Vec<SourceDuplicateFile>,
+    /// The normalized name the files were matched on (fuzzy mode only, e.g. "epic hit.wav")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalized_key: Option<String>,
+}
+
+/// Files with identical contents found anywhere in the library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentDuplicateGroup {
+    pub content_hash: String,
+    /// Every copy, the suggested keeper first
+    pub files: Vec<SourceDuplicateFile>,
+    /// Suggested file to keep (highest bitrate, then the shortest path)
+    pub keep_path: String,
 }
 
 /// A file that is part of a duplicate group
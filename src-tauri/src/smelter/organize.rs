@@ -1,269 +1,1435 @@
 // File organization logic
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::ErrorKind;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use unicode_normalization::UnicodeNormalization;
 
-use super::{AudioMetadata, DuplicateInfo, OrganizeResult, SourceDuplicateFile, SourceDuplicateGroup};
+use super::cancel::CancelToken;
+use super::fingerprint;
+use super::genre_map;
+use super::hash::hash_file;
+use super::metadata::is_supported_audio_file;
+use super::{
+    AudioMetadata, CategoryReason, ConflictStrategy, ContentDuplicateGroup, DuplicateInfo,
+    DuplicateMatchType, OrganizeOptions, OrganizeProgress, OrganizeResult, PlannedMove, PreviewEntry,
+    SmelterError, SourceDuplicateFile, SourceDuplicateGroup, SpaceCheck,
+};
 
-/// Format a filesystem error with user-friendly messages
-fn format_fs_error(e: &std::io::Error, path: &str, operation: &str) -> String {
-    match e.kind() {
-        ErrorKind::PermissionDenied => {
-            format!(
-                "Permission denied: Cannot {} '{}'. Try choosing a different folder or check folder permissions.",
-                operation, path
-            )
-        }
-        ErrorKind::NotFound => {
-            format!("File not found: '{}' may have been moved or deleted.", path)
-        }
-        ErrorKind::AlreadyExists => {
-            format!("File already exists at destination: '{}'", path)
-        }
-        ErrorKind::StorageFull | ErrorKind::QuotaExceeded => {
-            format!("Not enough disk space to {} '{}'.", operation, path)
-        }
-        _ => format!("Failed to {} '{}': {}", operation, path, e),
-    }
+/// Format a filesystem error with user-friendly messages (for per-file error lists)
+pub fn format_fs_error(e: &std::io::Error, path: &str, operation: &str) -> String {
+    SmelterError::from_io(e, path, operation).to_string()
 }
 
 /// Organize files into folders based on a category
+/// `on_progress` is called before each file and once more when all files are processed
+/// Stops between files when `cancel` is set, returning counts for the files already handled
 pub fn organize_files(
     files: &[AudioMetadata],
     output_folder: &str,
-    organize_by: &str,
-    operation: &str, // "move" or "copy"
-) -> Result<OrganizeResult, String> {
+    options: &OrganizeOptions,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(OrganizeProgress),
+) -> Result<OrganizeResult, SmelterError> {
+    let operation = options.operation.as_str();
     let output_path = Path::new(output_folder);
 
+    check_output_outside_sources(files, output_path)?;
+
     // Create output folder if it doesn't exist
-    fs::create_dir_all(output_path).map_err(|e| {
-        format_fs_error(&e, output_folder, "create output folder")
-    })?;
+    if !options.dry_run {
+        fs::create_dir_all(output_path).map_err(|e| {
+            SmelterError::from_io(&e, output_folder, "create output folder")
+        })?;
+    }
 
     let mut success_count = 0u32;
     let mut error_count = 0u32;
-    let skipped_count = 0u32;
+    let mut skipped_count = 0u32;
     let mut errors = Vec::new();
+    let mut planned = Vec::new();
+    let mut moved: Vec<(String, String)> = Vec::new();
+    // One row per file and category, written to manifest.csv when requested
+    let mut manifest: Vec<ManifestRow> = Vec::new();
 
-    // Track filenames per category to handle duplicates
-    let mut used_names: HashMap<String, HashMap<String, u32>> = HashMap::new();
+    // Destination names and skips, remembering what this run already placed
+    let mut planner = DestinationPlanner::default();
 
-    for file in files {
-        // Get the category (handles SFX detection automatically)
-        let category = get_file_category(file, organize_by);
+    // In atomic mode, every move recorded in `moved` is reversed on failure
+    let atomic = options.atomic && operation == "move" && !options.dry_run;
 
-        // Sanitize category name for filesystem
-        let safe_category = sanitize_folder_name(&category);
+    let total = files.len() as u32;
+    let mut cancelled = false;
+    let mut destination_unavailable = false;
 
-        // Create category folder
-        let category_path = output_path.join(&safe_category);
-        if let Err(e) = fs::create_dir_all(&category_path) {
-            errors.push(format_fs_error(&e, &safe_category, "create folder"));
-            error_count += 1;
-            continue;
+    'files: for (index, file) in files.iter().enumerate() {
+        if cancel.is_cancelled() {
+            cancelled = true;
+            break;
         }
 
-        // Generate unique filename
-        let filename = generate_unique_filename(
-            &category_path,
-            &file.filename,
-            &mut used_names,
-            &safe_category,
-        );
-
-        let dest_path = category_path.join(&filename);
+        on_progress(OrganizeProgress {
+            done: index as u32,
+            total,
+            current_filename: Some(file.filename.clone()),
+        });
 
-        // Perform the operation
-        let result = match operation {
-            "move" => fs::rename(&file.path, &dest_path)
-                .or_else(|_| {
-                    // rename fails across filesystems, try copy+delete
-                    fs::copy(&file.path, &dest_path)?;
-                    fs::remove_file(&file.path)
-                }),
-            "copy" => fs::copy(&file.path, &dest_path).map(|_| ()),
-            _ => {
-                errors.push(format!("Unknown operation: {}", operation));
+        // Get the sanitized (possibly nested) category paths (handles SFX detection automatically)
+        // There is more than one only when copying with `split_multivalue`
+        for safe_category in get_file_categories(file, options) {
+            // Create category folder (and any parent levels)
+            let category_path = output_path.join(&safe_category);
+            let create_result = if options.dry_run {
+                Ok(())
+            } else {
+                fs::create_dir_all(&category_path)
+            };
+            if let Err(e) = create_result {
+                if !output_path.is_dir() {
+                    errors.push(destination_unavailable_error(
+                        output_folder,
+                        index,
+                        files.len(),
+                    ));
+                    destination_unavailable = true;
+                    break 'files;
+                }
+                errors.push(format_fs_error(&e, &safe_category, "create folder"));
                 error_count += 1;
+                manifest.push(ManifestRow::new(file, String::new(), &safe_category, "failed"));
+                if atomic {
+                    success_count -= rollback_moves(&mut moved, &mut errors);
+                    break 'files;
+                }
                 continue;
             }
-        };
 
-        match result {
-            Ok(_) => success_count += 1,
-            Err(e) => {
-                errors.push(format_fs_error(&e, &file.filename, operation));
-                error_count += 1;
+            // Apply the filename template, if any
+            let target_name = match options.filename_template {
+                Some(ref template) => expand_filename_template(template, file),
+                None => file.filename.clone(),
+            };
+
+            // Resolve the destination filename, or skip files the destination already holds
+            let destination =
+                planner.resolve(file, &category_path, &safe_category, &target_name, options);
+            let (filename, merge_hash) = match destination {
+                Destination::Place {
+                    filename,
+                    merge_hash,
+                } => (filename, merge_hash),
+                Destination::Skip(existing) => {
+                    skipped_count += 1;
+                    let existing = existing.to_string_lossy().to_string();
+                    manifest.push(ManifestRow::new(file, existing, &safe_category, "skipped"));
+                    continue;
+                }
+                Destination::Failed(message) => {
+                    errors.push(message);
+                    error_count += 1;
+                    manifest.push(ManifestRow::new(file, String::new(), &safe_category, "failed"));
+                    continue;
+                }
+            };
+
+            let dest_path = category_path.join(&filename);
+
+            // Perform the operation (dry runs only validate it)
+            let result = match operation {
+                "move" | "copy" | "hardlink" | "symlink" if options.dry_run => Ok(()),
+                "move" => with_retry(options.retry_attempts, || {
+                    move_file(Path::new(&file.path), &dest_path)
+                }),
+                "copy" if options.verify_copies => with_retry(options.retry_attempts, || {
+                    copy_file(Path::new(&file.path), &dest_path)
+                })
+                .and_then(|_| verify_copy(&file.path, &dest_path)),
+                "copy" => with_retry(options.retry_attempts, || {
+                    copy_file(Path::new(&file.path), &dest_path)
+                }),
+                "hardlink" => fs::hard_link(&file.path, &dest_path),
+                "symlink" => create_symlink(Path::new(&file.path), &dest_path),
+                _ => {
+                    errors.push(format!("Unknown operation: {}", operation));
+                    error_count += 1;
+                    manifest.push(ManifestRow::new(file, String::new(), &safe_category, "failed"));
+                    continue;
+                }
+            };
+
+            match result {
+                Ok(_) => {
+                    success_count += 1;
+                    let destination = dest_path.to_string_lossy().to_string();
+                    manifest.push(ManifestRow::new(file, destination.clone(), &safe_category, "success"));
+                    if options.dry_run {
+                        planned.push(PlannedMove {
+                            source_path: file.path.clone(),
+                            destination_path: destination,
+                            category: safe_category.clone(),
+                        });
+                    } else {
+                        moved.push((file.path.clone(), destination));
+                    }
+                    planner.record(&category_path, dest_path, merge_hash);
+                }
+                Err(e) => {
+                    // The whole output folder is gone: every remaining file would fail the same way
+                    if !options.dry_run && !output_path.is_dir() {
+                        errors.push(destination_unavailable_error(
+                            output_folder,
+                            index,
+                            files.len(),
+                        ));
+                        destination_unavailable = true;
+                        break 'files;
+                    }
+                    errors.push(format_fs_error(&e, &file.filename, operation));
+                    error_count += 1;
+                    let destination = dest_path.to_string_lossy().to_string();
+                    manifest.push(ManifestRow::new(file, destination, &safe_category, "failed"));
+                    if atomic {
+                        success_count -= rollback_moves(&mut moved, &mut errors);
+                        break 'files;
+                    }
+                }
+            }
+        }
+    }
+
+    if options.write_manifest && !options.dry_run && !destination_unavailable {
+        // Files moved back by an atomic rollback are no longer at their destination
+        for row in manifest.iter_mut().filter(|row| row.status == "success") {
+            if !moved.iter().any(|(source, dest)| *source == row.source_path && *dest == row.destination_path) {
+                row.status = "rolled back";
             }
         }
+        let manifest_path = output_path.join(MANIFEST_FILENAME);
+        if let Err(e) = write_manifest(&manifest_path, &manifest, operation) {
+            errors.push(format_fs_error(&e, &manifest_path.to_string_lossy(), "write manifest"));
+        }
     }
 
+    // Always report completion, even if some files failed or the user cancelled
+    on_progress(OrganizeProgress {
+        done: total,
+        total,
+        current_filename: None,
+    });
+
     Ok(OrganizeResult {
         success_count,
         error_count,
         skipped_count,
         errors,
+        cancelled,
+        destination_unavailable,
+        planned,
+        moved,
+    })
+}
+
+/// What to do with one file in one category folder
+enum Destination {
+    /// Place it under `filename` (`merge_hash` is its content hash in Merge mode)
+    Place {
+        filename: String,
+        merge_hash: Option<String>,
+    },
+    /// Leave it out: the destination already holds it at this path
+    Skip(PathBuf),
+    /// Its content couldn't be compared with the folder's files
+    Failed(String),
+}
+
+/// Decides the destination of each file, remembering what the run has placed so far
+/// Shared by `organize_files` and `preview_final_names`, so the preview skips and renames
+/// exactly the files organizing would
+#[derive(Default)]
+struct DestinationPlanner {
+    /// Filenames taken per category, for collision suffixes
+    used_names: HashMap<String, HashMap<String, u32>>,
+    /// Destinations placed in this run (so Skip also works for same-named files in a dry run)
+    placed: HashSet<PathBuf>,
+    /// Merge mode: content hashes of files in each category folder, including ones placed this run
+    folder_hashes: HashMap<PathBuf, Vec<(PathBuf, String)>>,
+}
+
+impl DestinationPlanner {
+    /// Apply `skip_identical`, Merge's identical-content check and the conflict strategy
+    /// to `target_name` (the templated filename) in `category_path`
+    fn resolve(
+        &mut self,
+        file: &AudioMetadata,
+        category_path: &Path,
+        safe_category: &str,
+        target_name: &str,
+        options: &OrganizeOptions,
+    ) -> Destination {
+        // Already placed by an earlier run: the destination holds the same content
+        if options.skip_identical {
+            let candidate = truncate_filename(target_name, "", options.max_filename_bytes);
+            if let Some(existing) =
+                find_existing_file(category_path, &candidate, options.case_insensitive_names)
+                    .filter(|existing| is_identical_file(&file.path, existing))
+            {
+                return Destination::Skip(existing);
+            }
+        }
+
+        // Merge mode: an identical file already in the category folder means nothing to do
+        let mut merge_hash = None;
+        if options.conflict_strategy == ConflictStrategy::Merge {
+            let source_hash = match super::cache::get_content_hash(&file.path) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    return Destination::Failed(format!(
+                        "Cannot compare '{}': {}",
+                        file.filename, e
+                    ))
+                }
+            };
+            let existing = self
+                .folder_hashes
+                .entry(category_path.to_path_buf())
+                .or_insert_with(|| hash_folder_files(category_path));
+            if let Some((existing_path, _)) = existing.iter().find(|(_, hash)| *hash == source_hash)
+            {
+                return Destination::Skip(existing_path.clone());
+            }
+            merge_hash = Some(source_hash);
+        }
+
+        let filename = match options.conflict_strategy {
+            ConflictStrategy::Rename | ConflictStrategy::Merge => generate_unique_filename(
+                category_path,
+                target_name,
+                &mut self.used_names,
+                safe_category,
+                options.max_filename_bytes,
+                options.case_insensitive_names,
+            ),
+            ConflictStrategy::Skip => {
+                let target_name = truncate_filename(target_name, "", options.max_filename_bytes);
+                let candidate = category_path.join(&target_name);
+                let exists =
+                    find_existing_file(category_path, &target_name, options.case_insensitive_names)
+                        .is_some();
+                if exists || self.placed.contains(&candidate) {
+                    return Destination::Skip(candidate);
+                }
+                target_name
+            }
+            ConflictStrategy::Overwrite => {
+                truncate_filename(target_name, "", options.max_filename_bytes)
+            }
+        };
+        Destination::Place {
+            filename,
+            merge_hash,
+        }
+    }
+
+    /// Remember a file placed at `dest_path`, so later files in the run see it
+    fn record(&mut self, category_path: &Path, dest_path: PathBuf, merge_hash: Option<String>) {
+        // Later identical sources in this run are merged into this one
+        if let Some(hash) = merge_hash {
+            self.folder_hashes
+                .entry(category_path.to_path_buf())
+                .or_default()
+                .push((dest_path.clone(), hash));
+        }
+        self.placed.insert(dest_path);
+    }
+}
+
+/// Error reported once when the output folder vanishes mid-organize, instead of one per file
+fn destination_unavailable_error(output_folder: &str, index: usize, total: usize) -> String {
+    format!(
+        "Destination unavailable: '{}' can no longer be reached (was the drive disconnected?). Stopped with {} of {} files left.",
+        output_folder,
+        total - index,
+        total
+    )
+}
+
+/// Refuse an output folder nested inside a folder the files come from
+/// Organizing into it would mix output with sources, and a later scan would pick up the output.
+/// The source folder itself is allowed: that organizes in place, into category subfolders
+fn check_output_outside_sources(
+    files: &[AudioMetadata],
+    output_path: &Path,
+) -> Result<(), SmelterError> {
+    let output = canonicalize_existing(output_path);
+    let source_dirs: HashSet<&Path> = files
+        .iter()
+        .filter_map(|file| Path::new(&file.path).parent())
+        .collect();
+
+    for source_dir in source_dirs {
+        let Ok(source_dir) = fs::canonicalize(source_dir) else {
+            continue;
+        };
+        if output != source_dir && output.starts_with(&source_dir) {
+            return Err(SmelterError::InvalidInput(format!(
+                "Output folder '{}' is inside the source folder '{}'. Choose an output folder outside the folders being organized.",
+                output_path.display(),
+                source_dir.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Canonicalize a path that may not exist yet (its nearest existing ancestor is resolved)
+fn canonicalize_existing(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut current = path;
+    loop {
+        if let Ok(canonical) = fs::canonicalize(current) {
+            return missing.iter().rev().fold(canonical, |acc, part| acc.join(part));
+        }
+        match (current.parent(), current.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                current = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Name of the CSV written to the output folder with `write_manifest`
+pub const MANIFEST_FILENAME: &str = "manifest.csv";
+
+/// A manifest.csv row: where one file went and how that turned out
+struct ManifestRow {
+    source_path: String,
+    destination_path: String,
+    category: String,
+    /// "success", "skipped", "failed" or "rolled back"
+    status: &'static str,
+}
+
+impl ManifestRow {
+    fn new(file: &AudioMetadata, destination_path: String, category: &str, status: &'static str) -> Self {
+        Self {
+            source_path: file.path.clone(),
+            destination_path,
+            category: category.to_string(),
+            status,
+        }
+    }
+}
+
+/// Write the manifest rows as CSV (replacing any manifest from an earlier run)
+fn write_manifest(path: &Path, rows: &[ManifestRow], operation: &str) -> std::io::Result<()> {
+    let mut csv = String::from("source_path,destination_path,category,operation,status\n");
+    for row in rows {
+        let fields = [
+            row.source_path.as_str(),
+            row.destination_path.as_str(),
+            row.category.as_str(),
+            operation,
+            row.status,
+        ];
+        let line: Vec<String> = fields.into_iter().map(csv_field).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    fs::write(path, csv)
+}
+
+/// Quote a CSV field if it contains a comma, quote or line break (quotes are doubled)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Default number of retries after a transient move/copy error
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+
+/// Wait before each retry; later retries reuse the last delay
+const RETRY_DELAYS_MS: [u64; 3] = [50, 200, 500];
+
+/// Whether an error is likely to go away on its own (network drives, files briefly locked
+/// by another program); permission and missing-file errors never are
+fn is_transient_error(e: &std::io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    #[cfg(windows)]
+    if matches!(e.raw_os_error(), Some(32) | Some(33)) {
+        return true;
+    }
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+    )
+}
+
+/// Run a filesystem operation, retrying up to `attempts` more times with a growing delay
+/// while it fails with a transient error
+fn with_retry<T>(attempts: u32, op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    retry_with_sleep(attempts, std::thread::sleep, op)
+}
+
+/// with_retry, waiting between attempts with `sleep` (so tests don't have to)
+fn retry_with_sleep<T>(
+    attempts: u32,
+    mut sleep: impl FnMut(Duration),
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut retry = 0;
+    loop {
+        match op() {
+            Err(e) if retry < attempts && is_transient_error(&e) => {
+                let delay = RETRY_DELAYS_MS[(retry as usize).min(RETRY_DELAYS_MS.len() - 1)];
+                sleep(Duration::from_millis(delay));
+                retry += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Move a file, falling back to copy+delete when rename fails (e.g. across filesystems)
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::rename(from, to).or_else(|_| {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
     })
 }
 
-/// Check if a file is SFX (not an Epidemic Sound file)
-/// Epidemic Sound files start with "ES_" prefix (case-sensitive)
-fn is_sfx_file(filename: &str) -> bool {
-    !filename.starts_with("ES_")
+/// Copy a file, keeping the source's modification and access times on the copy
+/// Setting the timestamps is best-effort; the copy succeeds even if it fails
+fn copy_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::copy(from, to)?;
+
+    if let Ok(meta) = fs::metadata(from) {
+        let mtime = filetime::FileTime::from_last_modification_time(&meta);
+        let atime = filetime::FileTime::from_last_access_time(&meta);
+        if let Err(e) = filetime::set_file_times(to, atime, mtime) {
+            eprintln!("Could not preserve timestamps on {}: {}", to.display(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `existing` has the same contents as `source` (sizes first, then hashes)
+fn is_identical_file(source: &str, existing: &Path) -> bool {
+    let same_size = match (fs::metadata(source), fs::metadata(existing)) {
+        (Ok(a), Ok(b)) => a.len() == b.len(),
+        _ => false,
+    };
+    if !same_size {
+        return false;
+    }
+    match (hash_file(source), hash_file(&existing.to_string_lossy())) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Check that a copy has the same contents as its source (by content hash)
+/// A mismatched copy is removed so a corrupt file isn't left behind
+fn verify_copy(source: &str, copy: &Path) -> std::io::Result<()> {
+    let copy_str = copy.to_string_lossy();
+    let source_hash = hash_file(source).map_err(std::io::Error::other)?;
+    let copy_hash = hash_file(&copy_str).map_err(std::io::Error::other)?;
+
+    if source_hash != copy_hash {
+        let _ = fs::remove_file(copy);
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "verification failed, the copy's contents don't match the original (copy removed)",
+        ));
+    }
+    Ok(())
+}
+
+/// Create a symbolic link pointing at a file
+fn create_symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(original, link)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::symlink_file(original, link)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (original, link);
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "Symbolic links are not supported on this platform",
+        ))
+    }
+}
+
+/// Move previously organized files back to their original paths (newest first)
+/// Restored entries are removed from `moved`; failures are added to `errors`
+/// Returns how many files were restored
+fn rollback_moves(moved: &mut Vec<(String, String)>, errors: &mut Vec<String>) -> u32 {
+    let mut restored = 0u32;
+    let mut not_restored = Vec::new();
+
+    for (original, destination) in moved.drain(..).rev() {
+        match move_file(Path::new(&destination), Path::new(&original)) {
+            Ok(_) => restored += 1,
+            Err(e) => {
+                errors.push(format_fs_error(&e, &destination, "restore"));
+                not_restored.push((original, destination));
+            }
+        }
+    }
+
+    not_restored.reverse();
+    *moved = not_restored;
+
+    errors.push(format!(
+        "Rolled back {} moved file(s) after an error",
+        restored
+    ));
+    restored
+}
+
+/// Default music prefix (Epidemic Sound files start with "ES_")
+pub const DEFAULT_SFX_PREFIX: &str = "ES_";
+
+/// Check if a file is SFX (doesn't start with the music prefix, case-sensitive)
+/// An empty prefix disables SFX detection entirely
+fn is_sfx_file(filename: &str, sfx_prefix: &str) -> bool {
+    !sfx_prefix.is_empty() && !filename.starts_with(sfx_prefix)
+}
+
+/// Set `is_sfx` on scanned files with the same check organizing applies to `sfx_prefix`
+pub fn mark_sfx<'a>(files: impl IntoIterator<Item = &'a mut AudioMetadata>, sfx_prefix: &str) {
+    for file in files {
+        file.is_sfx = is_sfx_file(&file.filename, sfx_prefix);
+    }
 }
 
 /// Determine the category for a file, considering SFX detection
-fn get_file_category(file: &AudioMetadata, organize_by: &str) -> String {
-    // SFX files (without ES_ prefix) always go to SFX folder
-    if is_sfx_file(&file.filename) {
+/// Returns a relative folder path of sanitized components; `organize_by` values like
+/// "genre/mood" produce one folder level per field (e.g. `Rock/Energetic`)
+fn get_file_category(file: &AudioMetadata, options: &OrganizeOptions) -> String {
+    // Flatten mode puts every file directly in the output folder
+    if options.organize_by == "flatten" {
+        return String::new();
+    }
+
+    // SFX files (without the music prefix) always go to SFX folder
+    if is_sfx_file(&file.filename, &options.sfx_prefix) {
         return "SFX".to_string();
     }
 
-    // For ES_ files, use normal category resolution
-    let category = if let Some(ref override_cat) = file.category_override {
-        Some(override_cat.clone())
+    // For music files, use normal category resolution
+    if let Some(ref override_cat) = file.category_override {
+        return sanitize_folder_name(override_cat);
+    }
+
+    options
+        .organize_by
+        .split('/')
+        .map(|field| sanitize_folder_name(&get_field_category(file, field.trim())))
+        .collect::<Vec<_>>()
+        .join(std::path::MAIN_SEPARATOR_STR)
+}
+
+/// Explain which branch of get_file_category decided a file's category
+fn get_category_reason(file: &AudioMetadata, options: &OrganizeOptions) -> CategoryReason {
+    if options.organize_by == "flatten" {
+        return CategoryReason::Flattened;
+    }
+    if is_sfx_file(&file.filename, &options.sfx_prefix) {
+        return CategoryReason::SfxPrefix;
+    }
+    if file.category_override.is_some() {
+        return CategoryReason::Override;
+    }
+
+    let fields: Vec<&str> = options.organize_by.split('/').map(str::trim).collect();
+    if !fields.iter().all(|field| has_field_value(file, field)) {
+        return CategoryReason::FallbackUnknown;
+    }
+
+    match fields.first().copied() {
+        Some("genre") => CategoryReason::GenreTag,
+        Some("mood") => CategoryReason::MoodTag,
+        _ => CategoryReason::OtherTag,
+    }
+}
+
+/// Check whether a file has the tag an organize-by field is resolved from
+fn has_field_value(file: &AudioMetadata, field: &str) -> bool {
+    match field {
+        "genre" => file.genre.is_some(),
+        "mood" => file.mood.is_some(),
+        "energy" => file.energy_level.is_some(),
+        "artist" => file.artist.is_some(),
+        "year" => file.year.is_some(),
+        "bpm" => file.bpm.is_some(),
+        "album" => file.album.is_some(),
+        "duration" => file.duration_secs.is_some(),
+        "date_added" => file.file_modified.is_some(),
+        // Falls back to the filename, so there is always a letter
+        field if field == "alpha" || field.starts_with("alpha:") => true,
+        _ => custom_field_value(file, field).is_some(),
+    }
+}
+
+/// Determine every category a file should be placed in
+/// This is the single source of truth for organize, preview and both duplicate checks.
+/// With `split_multivalue` in copy mode, a file tagged "Happy, Bright" goes to both mood folders;
+/// otherwise (and always for moves, which can only have one destination) this is get_file_category.
+/// With `preserve_structure_root`, the file's subfolders below the root are kept under each category
+fn get_file_categories(file: &AudioMetadata, options: &OrganizeOptions) -> Vec<String> {
+    let categories = get_tag_categories(file, options);
+    match relative_subfolder(file, options) {
+        Some(subfolder) => categories
+            .iter()
+            .map(|category| Path::new(category).join(&subfolder).to_string_lossy().to_string())
+            .collect(),
+        None => categories,
+    }
+}
+
+/// Subfolders between `preserve_structure_root` and the file (e.g. `2023/January`)
+/// None if the option isn't set, the file sits directly in the root, or is outside it
+fn relative_subfolder(file: &AudioMetadata, options: &OrganizeOptions) -> Option<PathBuf> {
+    let root = options.preserve_structure_root.as_deref()?;
+    let relative = Path::new(&file.path).parent()?.strip_prefix(root).ok()?;
+    if relative.as_os_str().is_empty() {
+        None
     } else {
-        match organize_by {
-            "genre" => file.genre.clone(),
-            "mood" => file.mood.as_ref().map(|m| {
-                m.split(',')
-                    .next()
-                    .unwrap_or("Unknown")
-                    .trim()
-                    .to_string()
-            }),
-            _ => None,
+        Some(relative.to_path_buf())
+    }
+}
+
+/// Categories from the file's tags, before any preserved subfolders are added
+fn get_tag_categories(file: &AudioMetadata, options: &OrganizeOptions) -> Vec<String> {
+    if !options.split_multivalue
+        || options.operation != "copy"
+        || options.organize_by == "flatten"
+        || is_sfx_file(&file.filename, &options.sfx_prefix)
+        || file.category_override.is_some()
+    {
+        return vec![get_file_category(file, options)];
+    }
+
+    // Expand each organize-by level, combining nested levels (e.g. genre/mood)
+    let mut categories = vec![String::new()];
+    for field in options.organize_by.split('/') {
+        let values = get_field_values(file, field.trim());
+        categories = categories
+            .iter()
+            .flat_map(|parent| {
+                values.iter().map(move |value| {
+                    if parent.is_empty() {
+                        value.clone()
+                    } else {
+                        format!("{}{}{}", parent, std::path::MAIN_SEPARATOR_STR, value)
+                    }
+                })
+            })
+            .collect();
+    }
+    categories
+}
+
+/// Resolve a single organize-by field to every sanitized category name it lists
+/// Only mood is multi-valued (comma-separated); other fields produce a single value
+fn get_field_values(file: &AudioMetadata, field: &str) -> Vec<String> {
+    if field == "mood" {
+        if let Some(ref mood) = file.mood {
+            let mut values: Vec<String> = Vec::new();
+            for value in mood.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                let value = sanitize_folder_name(value);
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            if !values.is_empty() {
+                return values;
+            }
         }
+    }
+
+    vec![sanitize_folder_name(&get_field_category(file, field))]
+}
+
+/// Look up a "txxx:<name>" organize-by field in the file's custom fields (name is case-insensitive)
+fn custom_field_value<'a>(file: &'a AudioMetadata, field: &str) -> Option<&'a str> {
+    let name = field.strip_prefix("txxx:")?;
+    file.custom_fields
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Resolve a single organize-by field to a category name
+fn get_field_category(file: &AudioMetadata, field: &str) -> String {
+    let category = match field {
+        // Folded into broader buckets if the user loaded a genre map
+        "genre" => file.genre.as_deref().map(genre_map::map_genre),
+        "mood" => file.mood.as_ref().map(|m| {
+            m.split(',')
+                .next()
+                .unwrap_or("Unknown")
+                .trim()
+                .to_string()
+        }),
+        "energy" => file.energy_level.and_then(|level| {
+            // Numbered so folders sort from calm to intense
+            ENERGY_LABELS
+                .get(usize::from(level).checked_sub(1)?)
+                .map(|label| format!("{} - {}", level, label))
+        }),
+        "artist" => Some(
+            file.artist
+                .as_deref()
+                .and_then(primary_artist)
+                .unwrap_or_else(|| "Unknown Artist".to_string()),
+        ),
+        "duration" => Some(
+            file.duration_secs
+                .map(duration_bucket)
+                .unwrap_or_else(|| "Unknown Length".to_string()),
+        ),
+        "year" => file.year.map(|y| y.to_string()),
+        "date_added" => Some(
+            file.file_modified
+                .map(year_month)
+                .unwrap_or_else(|| "Unknown Date".to_string()),
+        ),
+        "bpm" => Some(
+            file.bpm
+                .map(|bpm| bpm_bucket(bpm, DEFAULT_BPM_BOUNDS))
+                .unwrap_or_else(|| "Unknown BPM".to_string()),
+        ),
+        "album" => file.album.as_ref().map(|album| match file.artist {
+            Some(ref artist) => format!("{} - {}", artist, album),
+            None => album.clone(),
+        }),
+        field if field == "alpha" || field.starts_with("alpha:") => Some(alpha_folder(file, field)),
+        _ => custom_field_value(file, field).map(str::to_string),
     };
 
     category.unwrap_or_else(|| "Unknown".to_string())
 }
 
+/// Folder for an "alpha" field: the uppercased first letter ("A"-"Z", accents dropped so
+/// "élan" is "E"), or "#" when the name starts with a digit or a non-Latin letter
+/// "alpha" and "alpha:artist" use the artist, falling back to the title and then the filename;
+/// "alpha:title" uses the title (or filename), "alpha:filename" the filename
+fn alpha_folder(file: &AudioMetadata, field: &str) -> String {
+    let stem = Path::new(&file.filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.filename.clone());
+    let candidates = match field {
+        "alpha:filename" => vec![None],
+        "alpha:title" => vec![file.title.as_deref()],
+        _ => vec![file.artist.as_deref(), file.title.as_deref()],
+    };
+    let name = candidates
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .find(|name| name.chars().any(char::is_alphanumeric))
+        .unwrap_or(&stem);
+
+    // NFD splits "é" into "e" plus a combining accent
+    match name.nfd().find(|c| c.is_alphanumeric()) {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase().to_string(),
+        _ => "#".to_string(),
+    }
+}
+
+/// Separators between a primary artist and featured/collaborating artists
+const ARTIST_SEPARATORS: &[&str] = &[",", "&", " feat.", " feat ", " ft.", " featuring "];
+
+/// Get the primary artist from a credit like "Artist A feat. B" or "Artist A & Artist B"
+fn primary_artist(artist: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets valid for slicing the original
+    let lower = artist.to_ascii_lowercase();
+    let end = ARTIST_SEPARATORS
+        .iter()
+        .filter_map(|sep| lower.find(sep))
+        .min()
+        .unwrap_or(artist.len());
+
+    let primary = artist[..end].trim();
+    if primary.is_empty() {
+        None
+    } else {
+        Some(primary.to_string())
+    }
+}
+
+/// Folder labels for energy levels 1-5
+const ENERGY_LABELS: [&str; 5] = ["Very Low", "Low", "Medium", "High", "Very High"];
+
+/// Map a duration to a length bucket label, separating stingers and loops from full tracks
+fn duration_bucket(duration_secs: f64) -> String {
+    let label = if duration_secs < 15.0 {
+        "0-15s"
+    } else if duration_secs < 60.0 {
+        "15-60s"
+    } else if duration_secs < 180.0 {
+        "1-3min"
+    } else {
+        "3min+"
+    };
+    label.to_string()
+}
+
+/// Format Unix seconds as a "YYYY-MM" folder name (UTC)
+fn year_month(secs: i64) -> String {
+    // Civil-from-days (Howard Hinnant's algorithm), avoiding a date crate for one conversion
+    let days = secs.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}", year, month)
+}
+
+/// Default tempo bucket boundaries, producing 0-60, 60-90, 90-120, 120-150 and 150+
+const DEFAULT_BPM_BOUNDS: &[u32] = &[60, 90, 120, 150];
+
+/// Map a BPM value to a tempo bucket label (lower bound inclusive, upper bound exclusive)
+fn bpm_bucket(bpm: u32, bounds: &[u32]) -> String {
+    let mut lower = 0;
+    for &upper in bounds {
+        if bpm < upper {
+            return format!("{}-{}", lower, upper);
+        }
+        lower = upper;
+    }
+    format!("{}+", lower)
+}
+
+/// Names Windows reserves for devices (invalid as folder names, even with an extension)
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 /// Sanitize a string for use as a folder name
+/// Also strips trailing dots/spaces and suffixes Windows reserved names (e.g. "CON" -> "CON_")
 fn sanitize_folder_name(name: &str) -> String {
-    name.chars()
+    // NFC, so the same tag value in composed and decomposed form maps to one folder
+    let mut sanitized = name
+        .nfc()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
             _ => c,
         })
         .collect::<String>()
         .trim()
-        .to_string()
+        // Windows silently drops trailing dots and spaces
+        .trim_end_matches(['.', ' '])
+        .to_string();
+
+    // "CON" and "con.mp3" are both reserved, so check the part before the first dot
+    let base_name = sanitized.split('.').next().unwrap_or("");
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base_name))
+    {
+        sanitized.insert(base_name.len(), '_');
+    }
+
+    if sanitized.is_empty() {
+        "Unknown".to_string()
+    } else {
+        sanitized
+    }
+}
+
+/// Build a filename from a template like "{artist} - {title}"
+/// Placeholders: {artist}, {title}, {genre}, {bpm}, {year}, {original} (original name without extension)
+/// Missing fields become "Unknown" and the original extension is always kept
+fn expand_filename_template(template: &str, file: &AudioMetadata) -> String {
+    let path = Path::new(&file.filename);
+    let original_stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(&file.filename);
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+
+    let values = [
+        ("{artist}", file.artist.clone()),
+        ("{title}", file.title.clone()),
+        ("{genre}", file.genre.clone()),
+        ("{bpm}", file.bpm.map(|b| b.to_string())),
+        ("{year}", file.year.map(|y| y.to_string())),
+        ("{original}", Some(original_stem.to_string())),
+    ];
+
+    let mut stem = template.to_string();
+    for (placeholder, value) in values {
+        if stem.contains(placeholder) {
+            let safe_value = sanitize_folder_name(value.as_deref().unwrap_or(""));
+            stem = stem.replace(placeholder, &safe_value);
+        }
+    }
+
+    // Templates may include the extension already (e.g. "{artist} - {title}.mp3")
+    let suffix = format!(".{}", ext);
+    if let Some(start) = stem.len().checked_sub(suffix.len()) {
+        if !ext.is_empty() && stem.is_char_boundary(start) && stem[start..].eq_ignore_ascii_case(&suffix) {
+            stem.truncate(start);
+        }
+    }
+
+    let stem = sanitize_folder_name(&stem);
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+/// Default filename length limit in bytes (the limit on most filesystems)
+pub const DEFAULT_MAX_FILENAME_BYTES: usize = 255;
+
+/// Build a filename with an optional suffix before the extension (e.g. "_12"),
+/// shortening the stem so the whole name fits in `max_bytes`
+/// The extension and suffix are always kept and the stem is cut on a UTF-8 character boundary
+fn truncate_filename(name: &str, suffix: &str, max_bytes: usize) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+
+    let ext_len = if ext.is_empty() { 0 } else { ext.len() + 1 };
+    let mut end = stem.len().min(max_bytes.saturating_sub(suffix.len() + ext_len));
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    let stem = &stem[..end];
+
+    if ext.is_empty() {
+        format!("{}{}", stem, suffix)
+    } else {
+        format!("{}{}.{}", stem, suffix, ext)
+    }
 }
 
 /// Generate a unique filename, handling duplicates
+/// Names are shortened to fit within `max_bytes`, including any numbering suffix
+/// With `case_insensitive`, names differing only in case (e.g. `Track.wav`/`track.wav`) collide
 fn generate_unique_filename(
     folder: &Path,
     original_name: &str,
     used_names: &mut HashMap<String, HashMap<String, u32>>,
     category: &str,
+    max_bytes: usize,
+    case_insensitive: bool,
 ) -> String {
     let category_names = used_names.entry(category.to_string()).or_default();
+    let name_key = filename_key(original_name, case_insensitive);
 
     // Check if this filename was already used in this category
-    if let Some(count) = category_names.get(original_name) {
+    if let Some(count) = category_names.get(&name_key) {
         // Generate numbered variant
-        let path = Path::new(original_name);
-        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(original_name);
-        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-
-        let new_name = if ext.is_empty() {
-            format!("{}_{}", stem, count + 1)
-        } else {
-            format!("{}_{}.{}", stem, count + 1, ext)
-        };
+        let new_name = truncate_filename(original_name, &format!("_{}", count + 1), max_bytes);
 
-        category_names.insert(original_name.to_string(), count + 1);
+        category_names.insert(name_key, count + 1);
         new_name
     } else {
         // Check if file already exists on disk
-        let dest = folder.join(original_name);
-        if dest.exists() {
+        let name = truncate_filename(original_name, "", max_bytes);
+        if find_existing_file(folder, &name, case_insensitive).is_some() {
             // Find a free number
-            let path = Path::new(original_name);
-            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(original_name);
-            let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-
             let mut counter = 1u32;
             loop {
-                let new_name = if ext.is_empty() {
-                    format!("{}_{}", stem, counter)
-                } else {
-                    format!("{}_{}.{}", stem, counter, ext)
-                };
+                let new_name = truncate_filename(original_name, &format!("_{}", counter), max_bytes);
 
-                if !folder.join(&new_name).exists() {
-                    category_names.insert(original_name.to_string(), counter);
+                if find_existing_file(folder, &new_name, case_insensitive).is_none() {
+                    category_names.insert(name_key, counter);
                     return new_name;
                 }
                 counter += 1;
             }
         } else {
-            category_names.insert(original_name.to_string(), 0);
-            original_name.to_string()
+            category_names.insert(name_key, 0);
+            name
         }
     }
 }
 
-/// Preview the organization without actually moving files
-/// Returns a map of category -> list of files
-pub fn preview_organization(
-    files: &[AudioMetadata],
-    organize_by: &str,
-) -> HashMap<String, Vec<String>> {
-    let mut preview: HashMap<String, Vec<String>> = HashMap::new();
-
-    for file in files {
-        // Get the category (handles SFX detection automatically)
-        let category = get_file_category(file, organize_by);
-        let safe_category = sanitize_folder_name(&category);
+/// Key for comparing filenames: Unicode NFC (macOS stores names decomposed, tags are usually
+/// composed, so "Café" can arrive in two forms), lowercased if case is ignored
+fn filename_key(name: &str, case_insensitive: bool) -> String {
+    let normalized: String = name.nfc().collect();
+    if case_insensitive {
+        normalized.to_lowercase()
+    } else {
+        normalized
+    }
+}
 
-        preview
-            .entry(safe_category)
-            .or_default()
-            .push(file.filename.clone());
+/// Find a file named `name` in a folder, optionally ignoring case
+/// Case-insensitive and Unicode-normalized lookups also work on filesystems that distinguish
+/// them by listing the folder
+fn find_existing_file(folder: &Path, name: &str, case_insensitive: bool) -> Option<PathBuf> {
+    let exact = folder.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    // An ASCII name has no other Unicode form, so only case could differ
+    if !case_insensitive && name.is_ascii() {
+        return None;
     }
 
-    preview
+    let key = filename_key(name, case_insensitive);
+    fs::read_dir(folder)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| filename_key(&e.file_name().to_string_lossy(), case_insensitive) == key)
+        .map(|e| e.path())
 }
 
-/// Find files that already exist in the target folders
-pub fn find_duplicates(
+/// Check whether the output folder's volume has room for the organized files
+/// Copies need their full size; moves only need space when crossing to another volume,
+/// and links need none
+pub fn check_space(
     files: &[AudioMetadata],
     output_folder: &str,
-    organize_by: &str,
-) -> Vec<DuplicateInfo> {
+    options: &OrganizeOptions,
+) -> Result<SpaceCheck, SmelterError> {
+    // The output folder may not exist yet, so measure its nearest existing ancestor
     let output_path = Path::new(output_folder);
-    let mut duplicates = Vec::new();
+    let existing = output_path
+        .ancestors()
+        .find(|p| p.exists())
+        .ok_or_else(|| {
+            SmelterError::NotFound(format!("Output folder '{}' is not on an available drive", output_folder))
+        })?;
 
-    for file in files {
-        // Get the category (handles SFX detection automatically)
-        let category = get_file_category(file, organize_by);
-        let safe_category = sanitize_folder_name(&category);
-        let target_path = output_path.join(&safe_category).join(&file.filename);
-
-        if target_path.exists() {
-            duplicates.push(DuplicateInfo {
-                source_path: file.path.clone(),
-                source_filename: file.filename.clone(),
-                existing_path: target_path.to_string_lossy().to_string(),
-                category: safe_category,
-            });
-        }
-    }
+    let available_bytes = fs2::available_space(existing)
+        .map_err(|e| SmelterError::from_io(&e, output_folder, "check free space on"))?;
 
-    duplicates
+    let required_bytes = files
+        .iter()
+        .filter(|file| match options.operation.as_str() {
+            "copy" => true,
+            "move" => !same_volume(Path::new(&file.path), existing),
+            _ => false,
+        })
+        .filter_map(|file| fs::metadata(&file.path).ok())
+        .map(|meta| meta.len())
+        .sum();
+
+    Ok(SpaceCheck {
+        required_bytes,
+        available_bytes,
+        sufficient: required_bytes <= available_bytes,
+    })
 }
 
-/// Delete duplicate files (the existing ones in target folders)
-pub fn delete_duplicates(paths: &[String]) -> Result<(u32, Vec<String>), String> {
+/// Check whether two existing paths are on the same volume (a move between them is a rename)
+fn same_volume(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev(),
+            _ => false,
+        }
+    }
+    #[cfg(windows)]
+    {
+        // Compare drive prefixes (e.g. "C:")
+        let prefix = |p: &Path| {
+            fs::canonicalize(p)
+                .ok()
+                .and_then(|p| p.components().next().map(|c| c.as_os_str().to_ascii_lowercase()))
+        };
+        prefix(a).is_some() && prefix(a) == prefix(b)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        // No way to tell, so assume a move copies the data
+        let _ = (a, b);
+        false
+    }
+}
+
+/// Preview the organization without actually moving files
+/// Returns a map of category -> list of files, each with the reason it landed there
+pub fn preview_organization(
+    files: &[AudioMetadata],
+    options: &OrganizeOptions,
+) -> HashMap<String, Vec<PreviewEntry>> {
+    let mut preview: HashMap<String, Vec<PreviewEntry>> = HashMap::new();
+
+    for file in files {
+        let reason = get_category_reason(file, options);
+
+        // Get the categories (handles SFX detection and multi-value splitting automatically)
+        for safe_category in get_file_categories(file, options) {
+            preview
+                .entry(safe_category)
+                .or_default()
+                .push(PreviewEntry {
+                    filename: file.filename.clone(),
+                    reason,
+                    final_filename: None,
+                    skipped: false,
+                });
+        }
+    }
+
+    preview
+}
+
+/// Preview the organization with the final filename each file will get in `output_folder`
+/// Names are resolved by the same planner `organize_files` uses: the filename template is
+/// applied, files the destination already holds are marked `skipped`, and (for "rename" and
+/// "merge") `_1`, `_2`, ... suffixes are added for names already taken
+pub fn preview_final_names(
+    files: &[AudioMetadata],
+    output_folder: &str,
+    options: &OrganizeOptions,
+) -> HashMap<String, Vec<PreviewEntry>> {
+    let output_path = Path::new(output_folder);
+    let mut preview: HashMap<String, Vec<PreviewEntry>> = HashMap::new();
+    let mut planner = DestinationPlanner::default();
+
+    for file in files {
+        let reason = get_category_reason(file, options);
+        let target_name = match options.filename_template {
+            Some(ref template) => expand_filename_template(template, file),
+            None => file.filename.clone(),
+        };
+
+        for safe_category in get_file_categories(file, options) {
+            let category_path = output_path.join(&safe_category);
+            let destination =
+                planner.resolve(file, &category_path, &safe_category, &target_name, options);
+            let (final_filename, skipped) = match destination {
+                Destination::Place {
+                    filename,
+                    merge_hash,
+                } => {
+                    planner.record(&category_path, category_path.join(&filename), merge_hash);
+                    (Some(filename), false)
+                }
+                Destination::Skip(existing) => (
+                    existing
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string()),
+                    true,
+                ),
+                Destination::Failed(_) => (None, false),
+            };
+            preview
+                .entry(safe_category)
+                .or_default()
+                .push(PreviewEntry {
+                    filename: file.filename.clone(),
+                    reason,
+                    final_filename,
+                    skipped,
+                });
+        }
+    }
+
+    preview
+}
+
+/// Find files that already exist in the target folders
+/// With `compare_content`, files are matched by content hash, so renamed copies are found
+/// and same-named files with different contents are not flagged
+/// `on_progress` is called before each file and once more at the end; when `cancel` is set,
+/// the duplicates found so far are returned
+pub fn find_duplicates(
+    files: &[AudioMetadata],
+    output_folder: &str,
+    options: &OrganizeOptions,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(OrganizeProgress),
+) -> Vec<DuplicateInfo> {
+    let output_path = Path::new(output_folder);
+    let mut duplicates = Vec::new();
+
+    // Hashes of files already in each category folder (computed once per folder)
+    let mut folder_hashes: HashMap<PathBuf, Vec<(PathBuf, String)>> = HashMap::new();
+    // Fingerprints of audio files already in each category folder (computed once per folder)
+    let mut folder_fingerprints: HashMap<PathBuf, Vec<(PathBuf, Vec<u32>)>> = HashMap::new();
+
+    let total = files.len() as u32;
+
+    for (index, file) in files.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        on_progress(OrganizeProgress {
+            done: index as u32,
+            total,
+            current_filename: Some(file.filename.clone()),
+        });
+
+        // Check every category the file would be placed in (handles SFX detection automatically)
+        for safe_category in get_file_categories(file, options) {
+            let category_path = output_path.join(&safe_category);
+
+            if options.compare_fingerprint {
+                let source_fingerprint = match super::cache::get_fingerprint(&file.path) {
+                    Ok(fingerprint) => fingerprint,
+                    Err(e) => {
+                        eprintln!("Error fingerprinting {}: {}", file.path, e);
+                        continue;
+                    }
+                };
+
+                let existing = folder_fingerprints
+                    .entry(category_path.clone())
+                    .or_insert_with(|| fingerprint_folder_files(&category_path));
+
+                // Ignore the source itself if it already lives in the target folder
+                let source_path = Path::new(&file.path);
+                if let Some((existing_path, _)) = existing.iter().find(|(path, fingerprint)| {
+                    path.as_path() != source_path
+                        && fingerprint::similarity(&source_fingerprint, fingerprint)
+                            >= FINGERPRINT_MATCH_THRESHOLD
+                }) {
+                    duplicates.push(DuplicateInfo {
+                        source_path: file.path.clone(),
+                        source_filename: file.filename.clone(),
+                        existing_path: existing_path.to_string_lossy().to_string(),
+                        category: safe_category,
+                        match_type: DuplicateMatchType::Fingerprint,
+                    });
+                }
+                continue;
+            }
+
+            if options.compare_content {
+                let source_hash = match super::cache::get_content_hash(&file.path) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        eprintln!("Error hashing {}: {}", file.path, e);
+                        continue;
+                    }
+                };
+
+                let existing = folder_hashes
+                    .entry(category_path.clone())
+                    .or_insert_with(|| hash_folder_files(&category_path));
+
+                // Ignore the source itself if it already lives in the target folder
+                let source_path = Path::new(&file.path);
+                if let Some((existing_path, _)) = existing
+                    .iter()
+                    .find(|(path, hash)| *hash == source_hash && path.as_path() != source_path)
+                {
+                    duplicates.push(DuplicateInfo {
+                        source_path: file.path.clone(),
+                        source_filename: file.filename.clone(),
+                        existing_path: existing_path.to_string_lossy().to_string(),
+                        category: safe_category,
+                        match_type: DuplicateMatchType::Content,
+                    });
+                }
+                continue;
+            }
+
+            if let Some(target_path) =
+                find_existing_file(&category_path, &file.filename, options.case_insensitive_names)
+            {
+                duplicates.push(DuplicateInfo {
+                    source_path: file.path.clone(),
+                    source_filename: file.filename.clone(),
+                    existing_path: target_path.to_string_lossy().to_string(),
+                    category: safe_category,
+                    match_type: DuplicateMatchType::Name,
+                });
+            }
+        }
+    }
+
+    on_progress(OrganizeProgress {
+        done: total,
+        total,
+        current_filename: None,
+    });
+
+    duplicates
+}
+
+/// Minimum fingerprint similarity (0.0-1.0) for two files to count as the same recording
+const FINGERPRINT_MATCH_THRESHOLD: f64 = 0.85;
+
+/// Fingerprint every audio file directly inside a folder (cached files skip decoding)
+fn fingerprint_folder_files(folder: &Path) -> Vec<(PathBuf, Vec<u32>)> {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && is_supported_audio_file(p))
+        .filter_map(|p| {
+            let fingerprint = super::cache::get_fingerprint(p.to_str()?).ok()?;
+            Some((p, fingerprint))
+        })
+        .collect()
+}
+
+/// Hash every file directly inside a folder (a missing folder has no files)
+fn hash_folder_files(folder: &Path) -> Vec<(PathBuf, String)> {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter_map(|p| {
+            let hash = hash_file(p.to_str()?).ok()?;
+            Some((p, hash))
+        })
+        .collect()
+}
+
+/// Delete duplicate files (the existing ones in target folders)
+/// Files go to the OS trash/recycle bin unless `permanent` is set
+pub fn delete_duplicates(
+    paths: &[String],
+    permanent: bool,
+) -> Result<(u32, Vec<String>), SmelterError> {
     let mut deleted_count = 0u32;
     let mut errors = Vec::new();
 
     for path in paths {
-        match fs::remove_file(path) {
+        let result = if permanent {
+            fs::remove_file(path)
+        } else {
+            move_to_trash(path)
+        };
+
+        match result {
             Ok(_) => deleted_count += 1,
             Err(e) => errors.push(format_fs_error(&e, path, "delete")),
         }
@@ -272,20 +1438,40 @@ pub fn delete_duplicates(paths: &[String]) -> Result<(u32, Vec<String>), String>
     Ok((deleted_count, errors))
 }
 
+/// Move a file to the OS trash
+/// Platforms without a trash (e.g. mobile) delete permanently; any other failure is returned
+/// so a file is never removed for good when the user expected to be able to restore it
+fn move_to_trash(path: &str) -> std::io::Result<()> {
+    // Report missing files as NotFound rather than a generic trash error
+    fs::metadata(path)?;
+
+    #[cfg(any(windows, all(unix, not(any(target_os = "ios", target_os = "android")))))]
+    {
+        trash::delete(path).map_err(|e| {
+            std::io::Error::new(ErrorKind::Other, format!("could not move to trash: {}", e))
+        })
+    }
+    #[cfg(not(any(windows, all(unix, not(any(target_os = "ios", target_os = "android"))))))]
+    {
+        fs::remove_file(path)
+    }
+}
+
 /// Find source files with the same filename that would go to the same category folder
 /// Returns groups of duplicates where each group has 2+ files with same name + category
+/// With `fuzzy`, names that differ only in case, spacing or separators (`Epic Hit.wav`,
+/// `Epic  Hit.wav`, `epic_hit.wav`) are grouped too, and each group carries the shared key
 pub fn find_source_duplicates(
     files: &[AudioMetadata],
-    organize_by: &str,
+    options: &OrganizeOptions,
+    fuzzy: bool,
 ) -> Vec<SourceDuplicateGroup> {
-    // Group files by (filename, category)
+    // Group files by (filename, category), NFC-normalizing names and lowercasing them if case is ignored
     let mut groups: HashMap<(String, String), Vec<SourceDuplicateFile>> = HashMap::new();
+    // First spelling seen for each name key, used for display
+    let mut display_names: HashMap<String, String> = HashMap::new();
 
     for file in files {
-        // Get the category (handles SFX detection automatically)
-        let category = get_file_category(file, organize_by);
-        let safe_category = sanitize_folder_name(&category);
-
         // Get parent folder name for display
         let folder = Path::new(&file.path)
             .parent()
@@ -294,21 +1480,1805 @@ pub fn find_source_duplicates(
             .unwrap_or("Unknown")
             .to_string();
 
-        let key = (file.filename.clone(), safe_category);
-        groups.entry(key).or_default().push(SourceDuplicateFile {
-            path: file.path.clone(),
-            folder,
-        });
+        // Group under every category the file would be placed in (handles SFX detection automatically)
+        let name_key = if fuzzy {
+            fuzzy_filename_key(&file.filename)
+        } else {
+            filename_key(&file.filename, options.case_insensitive_names)
+        };
+        display_names
+            .entry(name_key.clone())
+            .or_insert_with(|| file.filename.clone());
+
+        for safe_category in get_file_categories(file, options) {
+            let key = (name_key.clone(), safe_category);
+            groups.entry(key).or_default().push(SourceDuplicateFile {
+                path: file.path.clone(),
+                folder: folder.clone(),
+            });
+        }
     }
 
     // Filter to only groups with 2+ files (actual duplicates)
     groups
         .into_iter()
         .filter(|(_, files)| files.len() > 1)
-        .map(|((filename, category), files)| SourceDuplicateGroup {
-            filename,
+        .map(|((name_key, category), files)| SourceDuplicateGroup {
+            filename: display_names
+                .get(&name_key)
+                .cloned()
+                .unwrap_or_else(|| name_key.clone()),
             category,
             files,
+            normalized_key: fuzzy.then_some(name_key),
         })
         .collect()
 }
+
+/// Key for grouping near-identical filenames: NFC and lowercased, with every run of whitespace,
+/// separators and punctuation in the name collapsed to one space (the extension is kept)
+fn fuzzy_filename_key(name: &str) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let words: Vec<String> = stem
+        .nfc()
+        .collect::<String>()
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{}.{}", words.join(" "), ext.to_lowercase()),
+        None => words.join(" "),
+    }
+}
+
+/// Find files with identical contents anywhere in the input, regardless of name or category
+/// Only files sharing a size are hashed (in parallel within the hashing memory budget, via
+/// the cache); groups are sorted by the path of the suggested keeper
+pub fn find_all_duplicates(files: &[AudioMetadata]) -> Vec<ContentDuplicateGroup> {
+    use rayon::prelude::*;
+
+    // Files with a unique size can't have a duplicate, so skip hashing them
+    let mut by_size: HashMap<u64, Vec<&AudioMetadata>> = HashMap::new();
+    for file in files {
+        if let Ok(meta) = fs::metadata(&file.path) {
+            by_size.entry(meta.len()).or_default().push(file);
+        }
+    }
+    let candidates: Vec<&AudioMetadata> = by_size
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .flatten()
+        .collect();
+
+    let hashed: Vec<(String, &AudioMetadata)> = super::hash::with_hash_pool(|| {
+        candidates
+            .par_iter()
+            .filter_map(|file| match super::cache::get_content_hash(&file.path) {
+                Ok(hash) => Some((hash, *file)),
+                Err(e) => {
+                    eprintln!("Error hashing {}: {}", file.path, e);
+                    None
+                }
+            })
+            .collect()
+    });
+
+    let mut by_hash: HashMap<String, Vec<&AudioMetadata>> = HashMap::new();
+    for (hash, file) in hashed {
+        by_hash.entry(hash).or_default().push(file);
+    }
+
+    let mut groups: Vec<ContentDuplicateGroup> = by_hash
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .map(|(content_hash, mut group)| {
+            // Highest bitrate first, then the shortest path (closest to the library root)
+            group.sort_by(|a, b| {
+                b.bitrate_kbps
+                    .cmp(&a.bitrate_kbps)
+                    .then_with(|| a.path.len().cmp(&b.path.len()))
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+            ContentDuplicateGroup {
+                content_hash,
+                keep_path: group[0].path.clone(),
+                files: group
+                    .iter()
+                    .map(|file| SourceDuplicateFile {
+                        path: file.path.clone(),
+                        folder: Path::new(&file.path)
+                            .parent()
+                            .and_then(|p| p.file_name())
+                            .and_then(|n| n.to_str())
+                            .unwrap_or("Unknown")
+                            .to_string(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| a.keep_path.cmp(&b.keep_path));
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smelter::{cache, cancel};
+    use crate::test_support;
+    use std::path::PathBuf;
+
+    /// A scanned file at `path`, tagged with `genre`
+    fn audio_file(path: &str, genre: &str) -> AudioMetadata {
+        AudioMetadata {
+            path: path.to_string(),
+            filename: Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+            genre: Some(genre.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Options organizing by `organize_by` with the default music prefix
+    fn by(organize_by: &str) -> OrganizeOptions {
+        OrganizeOptions {
+            organize_by: organize_by.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn year_organizes_into_year_folders() {
+        let dated = AudioMetadata {
+            year: Some(2021),
+            ..audio_file("/sources/ES_Song.mp3", "Rock")
+        };
+        let undated = audio_file("/sources/ES_Other.mp3", "Rock");
+
+        assert_eq!(get_file_category(&dated, &by("year")), "2021");
+        assert_eq!(get_file_category(&undated, &by("year")), "Unknown");
+    }
+
+    #[test]
+    fn album_preview_groups_by_artist_and_album() {
+        let track = |path: &str, artist: Option<&str>, album: Option<&str>| AudioMetadata {
+            artist: artist.map(str::to_string),
+            album: album.map(str::to_string),
+            ..audio_file(path, "Soundtrack")
+        };
+        let files = [
+            track("/sources/ES_Opening.wav", Some("Composer"), Some("Score")),
+            track("/sources/ES_Finale.wav", Some("Composer"), Some("Score")),
+            track("/sources/ES_Bonus.wav", None, Some("Extras")),
+            track("/sources/ES_Single.wav", Some("Composer"), None),
+        ];
+
+        let preview = preview_organization(&files, &by("album"));
+        let names = |category: &str| -> Vec<&str> {
+            preview[category]
+                .iter()
+                .map(|entry| entry.filename.as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            names("Composer - Score"),
+            vec!["ES_Opening.wav", "ES_Finale.wav"]
+        );
+        assert_eq!(names("Extras"), vec!["ES_Bonus.wav"]);
+        assert_eq!(names("Unknown"), vec!["ES_Single.wav"]);
+    }
+
+    #[test]
+    fn bpm_lands_in_tempo_buckets() {
+        let cases = [
+            (Some(55), "0-60"),
+            (Some(60), "60-90"),
+            (Some(119), "90-120"),
+            (Some(128), "120-150"),
+            (Some(174), "150+"),
+            (None, "Unknown BPM"),
+        ];
+
+        for (bpm, bucket) in cases {
+            let file = AudioMetadata {
+                bpm,
+                ..audio_file("/sources/ES_Loop.wav", "House")
+            };
+            assert_eq!(
+                get_file_category(&file, &by("bpm")),
+                bucket,
+                "bpm {:?}",
+                bpm
+            );
+        }
+    }
+
+    #[test]
+    fn custom_prefix_decides_what_counts_as_sfx() {
+        let music = audio_file("/sources/AL_Theme.mp3", "Rock");
+        let epidemic = audio_file("/sources/ES_Theme.mp3", "Rock");
+
+        let custom = OrganizeOptions {
+            sfx_prefix: "AL_".to_string(),
+            ..by("genre")
+        };
+        let disabled = OrganizeOptions {
+            sfx_prefix: String::new(),
+            ..by("genre")
+        };
+
+        assert_eq!(get_file_category(&music, &custom), "Rock");
+        assert_eq!(get_file_category(&epidemic, &custom), "SFX");
+        assert_eq!(get_file_category(&epidemic, &by("genre")), "Rock");
+        // An empty prefix treats everything as music
+        assert_eq!(get_file_category(&music, &disabled), "Rock");
+    }
+
+    #[test]
+    fn is_sfx_flag_matches_the_sfx_folder() {
+        for options in [
+            by("genre"),
+            OrganizeOptions {
+                sfx_prefix: "AL_".to_string(),
+                ..by("genre")
+            },
+        ] {
+            let mut files = vec![
+                audio_file("/sources/AL_Theme.mp3", "Rock"),
+                audio_file("/sources/ES_Theme.mp3", "Rock"),
+            ];
+            mark_sfx(&mut files, &options.sfx_prefix);
+            for file in &files {
+                assert_eq!(
+                    file.is_sfx,
+                    get_file_category(file, &options) == "SFX",
+                    "{} with prefix {:?}",
+                    file.filename,
+                    options.sfx_prefix
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn genre_then_mood_nests_folders() {
+        let root = test_support::temp_dir("nested");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let path = test_support::write_file(&sources, "ES_Anthem.mp3", b"anthem");
+        let file = AudioMetadata {
+            mood: Some("Energetic, Uplifting".to_string()),
+            ..audio_file(&path, "Rock")
+        };
+        let options = OrganizeOptions {
+            organize_by: "genre/mood".to_string(),
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+        let nested = Path::new("Rock").join("Energetic");
+
+        let preview = preview_organization(std::slice::from_ref(&file), &options);
+        assert!(preview.contains_key(nested.to_str().unwrap()));
+
+        let output = root.join("out");
+        let result = organize_files(
+            &[file],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(result.success_count, 1);
+        assert!(output.join(&nested).join("ES_Anthem.mp3").is_file());
+    }
+
+    /// Copy a new `ES_Kick.wav` onto an output folder already holding a different one
+    /// Returns the result and the output's `Rock` folder
+    fn organize_onto_existing_kick(
+        name: &str,
+        strategy: ConflictStrategy,
+    ) -> (OrganizeResult, PathBuf) {
+        let root = test_support::temp_dir(name);
+        let sources = root.join("sources");
+        let existing = root.join("out").join("Rock");
+        fs::create_dir_all(&sources).unwrap();
+        fs::create_dir_all(&existing).unwrap();
+        test_support::write_file(&existing, "ES_Kick.wav", b"old kick");
+        let path = test_support::write_file(&sources, "ES_Kick.wav", b"new kick");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            conflict_strategy: strategy,
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &[audio_file(&path, "Rock")],
+            root.join("out").to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+        (result, existing)
+    }
+
+    #[test]
+    fn skip_leaves_the_existing_file() {
+        let (result, folder) = organize_onto_existing_kick("conflict_skip", ConflictStrategy::Skip);
+
+        assert_eq!(result.skipped_count, 1);
+        assert_eq!(result.success_count, 0);
+        assert_eq!(fs::read(folder.join("ES_Kick.wav")).unwrap(), b"old kick");
+        assert!(!folder.join("ES_Kick_1.wav").exists());
+    }
+
+    #[test]
+    fn overwrite_replaces_the_existing_file() {
+        let (result, folder) =
+            organize_onto_existing_kick("conflict_overwrite", ConflictStrategy::Overwrite);
+
+        assert_eq!(result.success_count, 1);
+        assert_eq!(fs::read(folder.join("ES_Kick.wav")).unwrap(), b"new kick");
+        assert!(!folder.join("ES_Kick_1.wav").exists());
+    }
+
+    #[test]
+    fn rename_keeps_both_files() {
+        let (result, folder) =
+            organize_onto_existing_kick("conflict_rename", ConflictStrategy::Rename);
+
+        assert_eq!(result.success_count, 1);
+        assert_eq!(fs::read(folder.join("ES_Kick.wav")).unwrap(), b"old kick");
+        assert_eq!(fs::read(folder.join("ES_Kick_1.wav")).unwrap(), b"new kick");
+    }
+
+    #[test]
+    fn cancelling_stops_before_the_next_file() {
+        let root = test_support::temp_dir("cancel");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let files: Vec<AudioMetadata> = ["ES_One.wav", "ES_Two.wav", "ES_Three.wav"]
+            .iter()
+            .map(|name| audio_file(&test_support::write_file(&sources, name, b"audio"), "Rock"))
+            .collect();
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+        let token = cancel::register("organize-test");
+        let output = root.join("out");
+
+        // Cancel while the second file is being organized; it still finishes, the third doesn't start
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &token,
+            |progress| {
+                if progress.done == 1 {
+                    assert!(cancel::cancel("organize-test"));
+                }
+            },
+        )
+        .unwrap();
+        cancel::unregister("organize-test");
+
+        assert!(result.cancelled);
+        assert_eq!(result.success_count, 2);
+        assert!(output.join("Rock").join("ES_Two.wav").is_file());
+        assert!(!output.join("Rock").join("ES_Three.wav").exists());
+        assert!(!cancel::cancel("organize-test"));
+    }
+
+    #[test]
+    fn atomic_move_restores_every_file_after_a_failure() {
+        let root = test_support::temp_dir("atomic");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let first = test_support::write_file(&sources, "ES_One.wav", b"one");
+        let second = test_support::write_file(&sources, "ES_Two.wav", b"two");
+        // Deleted before organizing, so moving it fails after the first two moved
+        let missing = sources.join("ES_Three.wav").to_string_lossy().to_string();
+        let files = [
+            audio_file(&first, "Rock"),
+            audio_file(&second, "Jazz"),
+            audio_file(&missing, "Rock"),
+        ];
+        let options = OrganizeOptions {
+            atomic: true,
+            ..Default::default()
+        };
+        let output = root.join("out");
+
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 0);
+        assert_eq!(result.error_count, 1);
+        assert!(result.moved.is_empty());
+        assert_eq!(fs::read(&first).unwrap(), b"one");
+        assert_eq!(fs::read(&second).unwrap(), b"two");
+        assert!(!output.join("Rock").join("ES_One.wav").exists());
+        assert!(!output.join("Jazz").join("ES_Two.wav").exists());
+    }
+
+    // Creating symlinks on Windows needs developer mode or admin rights
+    #[cfg(unix)]
+    #[test]
+    fn symlink_points_at_the_source() {
+        let root = test_support::temp_dir("symlink");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let path = test_support::write_file(&sources, "ES_Pad.wav", b"pad");
+        let options = OrganizeOptions {
+            operation: "symlink".to_string(),
+            ..Default::default()
+        };
+        let output = root.join("out");
+
+        let result = organize_files(
+            &[audio_file(&path, "Ambient")],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        let link = output.join("Ambient").join("ES_Pad.wav");
+        assert_eq!(result.success_count, 1);
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new(&path));
+        assert_eq!(fs::read(&path).unwrap(), b"pad");
+    }
+
+    #[test]
+    fn filename_template_fills_present_and_missing_fields() {
+        let tagged = AudioMetadata {
+            artist: Some("AC/DC".to_string()),
+            title: Some("Thunder".to_string()),
+            bpm: Some(133),
+            ..audio_file("/sources/ES_Track.mp3", "Rock")
+        };
+        let untagged = audio_file("/sources/ES_Track.mp3", "Rock");
+
+        assert_eq!(
+            expand_filename_template("{artist} - {title}", &tagged),
+            "AC_DC - Thunder.mp3"
+        );
+        assert_eq!(
+            expand_filename_template("{title} ({bpm}).mp3", &tagged),
+            "Thunder (133).mp3"
+        );
+        assert_eq!(
+            expand_filename_template("{artist} - {title} [{year}]", &untagged),
+            "Unknown - Unknown [Unknown].mp3"
+        );
+        assert_eq!(
+            expand_filename_template("{original}_{genre}", &untagged),
+            "ES_Track_Rock.mp3"
+        );
+    }
+
+    /// Dry-run options organizing by genre with the given conflict strategy
+    fn dry_run_options(conflict_strategy: ConflictStrategy) -> OrganizeOptions {
+        OrganizeOptions {
+            conflict_strategy,
+            dry_run: true,
+            ..Default::default()
+        }
+    }
+
+    /// Two different `ES_Drums.wav` music files from separate source folders
+    fn two_drums(root: &Path) -> Vec<AudioMetadata> {
+        ["a", "b"]
+            .iter()
+            .map(|folder| {
+                let dir = root.join("sources").join(folder);
+                fs::create_dir_all(&dir).unwrap();
+                let path = test_support::write_file(&dir, "ES_Drums.wav", folder.as_bytes());
+                audio_file(&path, "Rock")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dry_run_plans_what_a_real_run_does() {
+        let root = test_support::temp_dir("dry_run");
+        let files = two_drums(&root);
+        let output = root.join("out");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let dry = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+        assert!(!output.exists());
+
+        let real = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &OrganizeOptions {
+                dry_run: false,
+                ..options
+            },
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        let planned: Vec<&str> = dry
+            .planned
+            .iter()
+            .map(|planned| planned.destination_path.as_str())
+            .collect();
+        let rock = output.join("Rock");
+        assert_eq!(
+            planned,
+            [rock.join("ES_Drums.wav"), rock.join("ES_Drums_1.wav")]
+                .iter()
+                .map(|path| path.to_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(dry.success_count, real.success_count);
+        assert!(real.planned.is_empty());
+        assert_eq!(fs::read(rock.join("ES_Drums_1.wav")).unwrap(), b"b");
+    }
+
+    #[test]
+    fn content_duplicates_are_found_under_another_name() {
+        let root = test_support::temp_dir("content_duplicates");
+        let sources = root.join("sources");
+        let existing = root.join("out").join("Rock");
+        fs::create_dir_all(&sources).unwrap();
+        fs::create_dir_all(&existing).unwrap();
+        let renamed = test_support::write_file(&existing, "ES_Riff (final).wav", b"riff");
+        test_support::write_file(&existing, "ES_Solo.wav", b"other solo");
+        let riff = test_support::write_file(&sources, "ES_Riff.wav", b"riff");
+        let solo = test_support::write_file(&sources, "ES_Solo.wav", b"solo");
+        let files = [audio_file(&riff, "Rock"), audio_file(&solo, "Rock")];
+        let output = root.join("out");
+        let output = output.to_str().unwrap();
+
+        let by_content = OrganizeOptions {
+            compare_content: true,
+            ..Default::default()
+        };
+        let duplicates =
+            find_duplicates(&files, output, &by_content, &CancelToken::default(), |_| {});
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].source_path, riff);
+        assert_eq!(duplicates[0].existing_path, renamed);
+        assert_eq!(duplicates[0].match_type, DuplicateMatchType::Content);
+
+        // By name, only the same-named (but different) solo is flagged
+        let duplicates = find_duplicates(
+            &files,
+            output,
+            &OrganizeOptions::default(),
+            &CancelToken::default(),
+            |_| {},
+        );
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].source_path, solo);
+        assert_eq!(duplicates[0].match_type, DuplicateMatchType::Name);
+    }
+
+    #[test]
+    fn trashing_removes_the_file_from_its_folder() {
+        let dir = test_support::temp_dir("trash");
+        let path = test_support::write_file(&dir, "ES_Duplicate.wav", b"duplicate");
+
+        move_to_trash(&path).unwrap();
+
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn trashing_a_missing_file_reports_not_found() {
+        let dir = test_support::temp_dir("trash_missing");
+        let path = dir.join("ES_Gone.wav");
+
+        let err = move_to_trash(path.to_str().unwrap()).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn moved_lists_the_files_that_succeeded() {
+        let root = test_support::temp_dir("moved");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let kept = test_support::write_file(&sources, "ES_Kept.wav", b"kept");
+        let missing = sources.join("ES_Missing.wav").to_string_lossy().to_string();
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+        let output = root.join("out");
+
+        let result = organize_files(
+            &[audio_file(&missing, "Rock"), audio_file(&kept, "Jazz")],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        let destination = output.join("Jazz").join("ES_Kept.wav");
+        assert_eq!(result.error_count, 1);
+        assert_eq!(
+            result.moved,
+            vec![(kept, destination.to_string_lossy().to_string())]
+        );
+        assert!(destination.is_file());
+    }
+
+    #[test]
+    fn artist_groups_by_primary_artist() {
+        let credited = |artist: Option<&str>| AudioMetadata {
+            artist: artist.map(str::to_string),
+            ..audio_file("/sources/ES_Song.mp3", "Pop")
+        };
+        let cases = [
+            (Some("Artist A feat. B"), "Artist A"),
+            (Some("Artist A FT. B"), "Artist A"),
+            (Some("Artist A & Artist B"), "Artist A"),
+            (Some("Artist A, Artist B"), "Artist A"),
+            (Some("AC/DC"), "AC_DC"),
+            (None, "Unknown Artist"),
+        ];
+
+        for (artist, folder) in cases {
+            assert_eq!(
+                get_file_category(&credited(artist), &by("artist")),
+                folder,
+                "artist {:?}",
+                artist
+            );
+        }
+    }
+
+    #[test]
+    fn split_multivalue_copies_into_every_mood_folder() {
+        let root = test_support::temp_dir("split_moods");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let path = test_support::write_file(&sources, "ES_Sunrise.wav", b"sunrise");
+        let file = AudioMetadata {
+            mood: Some("Happy, Energetic, Bright".to_string()),
+            ..audio_file(&path, "Pop")
+        };
+        let copy = OrganizeOptions {
+            organize_by: "mood".to_string(),
+            operation: "copy".to_string(),
+            split_multivalue: true,
+            ..Default::default()
+        };
+        let output = root.join("out");
+
+        let result = organize_files(
+            std::slice::from_ref(&file),
+            output.to_str().unwrap(),
+            &copy,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 3);
+        for mood in ["Happy", "Energetic", "Bright"] {
+            assert!(
+                output.join(mood).join("ES_Sunrise.wav").is_file(),
+                "{}",
+                mood
+            );
+        }
+        assert!(Path::new(&path).is_file());
+
+        // Moves only ever have one destination
+        let moving = OrganizeOptions {
+            operation: "move".to_string(),
+            ..copy
+        };
+        assert_eq!(
+            get_file_categories(&file, &moving),
+            vec!["Happy".to_string()]
+        );
+    }
+
+    #[test]
+    fn folder_names_are_valid_on_windows() {
+        let cases = [
+            ("CON", "CON_"),
+            ("con", "con_"),
+            ("Aux.Mix", "Aux_.Mix"),
+            ("COM1", "COM1_"),
+            ("Console", "Console"),
+            ("Ambient...", "Ambient"),
+            ("Lo-Fi . ", "Lo-Fi"),
+            ("AC/DC", "AC_DC"),
+            ("...", "Unknown"),
+            ("", "Unknown"),
+        ];
+
+        for (name, expected) in cases {
+            assert_eq!(sanitize_folder_name(name), expected, "name {:?}", name);
+        }
+    }
+
+    #[test]
+    fn long_filenames_are_shortened_keeping_the_extension() {
+        let long_name = format!("{}.wav", "a".repeat(300));
+
+        let name = truncate_filename(&long_name, "", DEFAULT_MAX_FILENAME_BYTES);
+        assert!(name.len() <= DEFAULT_MAX_FILENAME_BYTES);
+        assert!(name.ends_with(".wav"));
+
+        let numbered = truncate_filename(&long_name, "_12", DEFAULT_MAX_FILENAME_BYTES);
+        assert!(numbered.len() <= DEFAULT_MAX_FILENAME_BYTES);
+        assert!(numbered.ends_with("_12.wav"));
+
+        // Multi-byte characters are never split
+        let accented = format!("{}.wav", "é".repeat(200));
+        let name = truncate_filename(&accented, "", DEFAULT_MAX_FILENAME_BYTES);
+        assert!(name.len() <= DEFAULT_MAX_FILENAME_BYTES);
+        assert!(name.trim_end_matches(".wav").chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn copies_keep_the_source_modification_time() {
+        let root = test_support::temp_dir("copy_mtime");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let source = test_support::write_file(&sources, "ES_Old.wav", b"old");
+        let old_mtime = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&source, old_mtime).unwrap();
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+        let output = root.join("out");
+
+        let result = organize_files(
+            &[audio_file(&source, "Rock")],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 1);
+        let copied = fs::metadata(output.join("Rock").join("ES_Old.wav")).unwrap();
+        assert_eq!(
+            filetime::FileTime::from_last_modification_time(&copied),
+            old_mtime
+        );
+    }
+
+    #[test]
+    fn verified_copies_succeed_when_contents_match() {
+        let root = test_support::temp_dir("verify_copy");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let source = test_support::write_file(&sources, "ES_Kick.wav", b"kick");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            verify_copies: true,
+            ..Default::default()
+        };
+        let output = root.join("out");
+
+        let result = organize_files(
+            &[audio_file(&source, "Rock")],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert_eq!(result.error_count, 0);
+        assert!(output.join("Rock").join("ES_Kick.wav").is_file());
+    }
+
+    #[test]
+    fn mismatched_copy_fails_verification_and_is_removed() {
+        let dir = test_support::temp_dir("verify_mismatch");
+        let source = test_support::write_file(&dir, "source.wav", b"original");
+        let corrupt = test_support::write_file(&dir, "copy.wav", b"corrupted");
+
+        let err = verify_copy(&source, Path::new(&corrupt)).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(!Path::new(&corrupt).exists());
+        assert!(Path::new(&source).exists());
+    }
+
+    #[test]
+    fn space_check_counts_copies_but_not_same_volume_moves() {
+        let root = test_support::temp_dir("space_check");
+        let source = test_support::write_file(&root, "ES_Kick.wav", &[0u8; 1000]);
+        let files = [audio_file(&source, "Rock")];
+        let output = root.join("out");
+        let check = |operation: &str| {
+            let options = OrganizeOptions {
+                operation: operation.to_string(),
+                ..Default::default()
+            };
+            check_space(&files, output.to_str().unwrap(), &options).unwrap()
+        };
+
+        let copy = check("copy");
+        assert_eq!(copy.required_bytes, 1000);
+        assert!(copy.sufficient);
+        assert_eq!(check("move").required_bytes, 0);
+        assert_eq!(check("symlink").required_bytes, 0);
+    }
+
+    #[test]
+    fn space_check_flags_files_larger_than_the_free_space() {
+        let root = test_support::temp_dir("space_check_huge");
+        let available = fs2::available_space(&root).unwrap();
+        // A sparse file claims more bytes than the volume has free without using them
+        let huge = root.join("ES_Huge.wav");
+        let file = fs::File::create(&huge).unwrap();
+        if file.set_len(available + 1).is_err() {
+            eprintln!("Skipping: the filesystem doesn't support sparse files this large");
+            return;
+        }
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+
+        let check = check_space(
+            &[audio_file(huge.to_str().unwrap(), "Rock")],
+            root.join("out").to_str().unwrap(),
+            &options,
+        )
+        .unwrap();
+
+        assert!(check.required_bytes > check.available_bytes);
+        assert!(!check.sufficient);
+    }
+
+    #[test]
+    fn duration_organizes_into_length_buckets() {
+        let lasting = |duration_secs: Option<f64>| AudioMetadata {
+            duration_secs,
+            ..audio_file("/sources/ES_Song.mp3", "Rock")
+        };
+        let cases = [
+            (Some(3.2), "0-15s"),
+            (Some(15.0), "15-60s"),
+            (Some(59.9), "15-60s"),
+            (Some(95.0), "1-3min"),
+            (Some(180.0), "3min+"),
+            (None, "Unknown Length"),
+        ];
+
+        for (duration_secs, expected) in cases {
+            assert_eq!(
+                get_file_category(&lasting(duration_secs), &by("duration")),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn preview_organize_and_duplicate_checks_agree_on_categories() {
+        let root = test_support::temp_dir("categories_agree");
+        let output = root.join("out");
+        let mut files = Vec::new();
+        for folder in ["a", "b"] {
+            let dir = root.join("sources").join(folder);
+            fs::create_dir_all(&dir).unwrap();
+            for name in [
+                "Whoosh.wav",
+                "ES_Song.wav",
+                "ES_NoTags.wav",
+                "ES_Override.wav",
+            ] {
+                let path = test_support::write_file(&dir, name, folder.as_bytes());
+                let mut file = audio_file(&path, "Rock");
+                match name {
+                    "ES_Song.wav" => file.mood = Some("Calm".to_string()),
+                    "ES_NoTags.wav" => file.genre = None,
+                    "ES_Override.wav" => file.category_override = Some("Trailers".to_string()),
+                    _ => {}
+                }
+                files.push(file);
+            }
+        }
+        let options = OrganizeOptions {
+            organize_by: "genre/mood".to_string(),
+            dry_run: true,
+            ..Default::default()
+        };
+        let file_name = |path: &str| {
+            Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let mut previewed = HashMap::new();
+        for (category, entries) in preview_organization(&files, &options) {
+            for entry in entries {
+                previewed.insert(entry.filename, category.clone());
+            }
+        }
+        assert_eq!(previewed.len(), 4);
+
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+        let organized: HashMap<String, String> = result
+            .planned
+            .iter()
+            .map(|planned| (file_name(&planned.source_path), planned.category.clone()))
+            .collect();
+        assert_eq!(organized, previewed);
+
+        let source_groups: HashMap<String, String> =
+            find_source_duplicates(&files, &options, false)
+                .into_iter()
+                .map(|group| (group.filename, group.category))
+                .collect();
+        assert_eq!(source_groups, previewed);
+
+        // Put each file where organizing would, so every source is reported as a duplicate
+        for (name, category) in &previewed {
+            let folder = output.join(category);
+            fs::create_dir_all(&folder).unwrap();
+            test_support::write_file(&folder, name, b"placed");
+        }
+        let duplicates: HashMap<String, String> = find_duplicates(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .into_iter()
+        .map(|duplicate| (duplicate.source_filename, duplicate.category))
+        .collect();
+        assert_eq!(duplicates, previewed);
+    }
+
+    #[test]
+    fn names_differing_in_case_group_when_case_is_ignored() {
+        let files = [
+            audio_file("/sources/a/ES_Hit.wav", "Rock"),
+            audio_file("/sources/b/ES_hit.wav", "Rock"),
+        ];
+        let mut options = OrganizeOptions {
+            case_insensitive_names: true,
+            ..Default::default()
+        };
+
+        let groups = find_source_duplicates(&files, &options, false);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 2);
+
+        options.case_insensitive_names = false;
+        assert!(find_source_duplicates(&files, &options, false).is_empty());
+    }
+
+    #[test]
+    fn preview_explains_each_category() {
+        let untagged = AudioMetadata {
+            genre: None,
+            ..audio_file("/sources/ES_Untagged.wav", "Rock")
+        };
+        let overridden = AudioMetadata {
+            category_override: Some("Trailers".to_string()),
+            ..audio_file("/sources/ES_Trailer.wav", "Rock")
+        };
+        let files = [
+            audio_file("/sources/Whoosh.wav", "Rock"),
+            audio_file("/sources/ES_Song.wav", "Rock"),
+            untagged,
+            overridden,
+        ];
+
+        let preview = preview_organization(&files, &OrganizeOptions::default());
+
+        assert_eq!(preview["SFX"][0].reason, CategoryReason::SfxPrefix);
+        assert_eq!(preview["Rock"][0].reason, CategoryReason::GenreTag);
+        assert_eq!(
+            preview["Unknown"][0].reason,
+            CategoryReason::FallbackUnknown
+        );
+        assert_eq!(preview["Trailers"][0].reason, CategoryReason::Override);
+    }
+
+    #[test]
+    fn merge_skips_identical_files_and_renames_the_rest() {
+        let _databases = test_support::use_test_databases();
+        cache::init_database().unwrap();
+        let root = test_support::temp_dir("merge");
+        let sources = root.join("sources");
+        let library = root.join("library");
+        let rock = library.join("Rock");
+        fs::create_dir_all(&sources).unwrap();
+        fs::create_dir_all(&rock).unwrap();
+        test_support::write_file(&rock, "ES_Same.wav", b"same");
+        test_support::write_file(&rock, "ES_Clash.wav", b"old take");
+        let files = [
+            audio_file(
+                &test_support::write_file(&sources, "ES_Same.wav", b"same"),
+                "Rock",
+            ),
+            audio_file(
+                &test_support::write_file(&sources, "ES_Clash.wav", b"new take"),
+                "Rock",
+            ),
+        ];
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            conflict_strategy: ConflictStrategy::Merge,
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &files,
+            library.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.skipped_count, 1);
+        assert_eq!(result.success_count, 1);
+        assert_eq!(fs::read(rock.join("ES_Clash.wav")).unwrap(), b"old take");
+        assert_eq!(fs::read(rock.join("ES_Clash_1.wav")).unwrap(), b"new take");
+    }
+
+    #[test]
+    fn transient_errors_are_retried_until_the_operation_succeeds() {
+        let mut calls = 0;
+        let mut delays = Vec::new();
+
+        let result = retry_with_sleep(
+            3,
+            |delay| delays.push(delay),
+            || {
+                calls += 1;
+                if calls <= 2 {
+                    Err(std::io::Error::from(ErrorKind::Interrupted))
+                } else {
+                    Ok(calls)
+                }
+            },
+        );
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(
+            delays,
+            [Duration::from_millis(50), Duration::from_millis(200)]
+        );
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        for kind in [ErrorKind::PermissionDenied, ErrorKind::NotFound] {
+            let mut calls = 0;
+            let mut delays = Vec::new();
+
+            let result: std::io::Result<()> = retry_with_sleep(
+                3,
+                |delay| delays.push(delay),
+                || {
+                    calls += 1;
+                    Err(std::io::Error::from(kind))
+                },
+            );
+
+            assert_eq!(result.unwrap_err().kind(), kind);
+            assert_eq!(calls, 1);
+            assert!(delays.is_empty());
+        }
+    }
+
+    #[test]
+    fn retries_give_up_after_the_configured_attempts() {
+        let mut calls = 0;
+
+        let result: std::io::Result<()> = retry_with_sleep(
+            2,
+            |_| {},
+            || {
+                calls += 1;
+                Err(std::io::Error::from(ErrorKind::TimedOut))
+            },
+        );
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn flatten_collects_every_file_into_the_output_root() {
+        let root = test_support::temp_dir("flatten");
+        let sources = root.join("sources");
+        let output = root.join("output");
+        let drums = sources.join("Drums");
+        let keys = sources.join("Keys").join("Takes");
+        fs::create_dir_all(&drums).unwrap();
+        fs::create_dir_all(&keys).unwrap();
+        let files = [
+            audio_file(
+                &test_support::write_file(&drums, "ES_Loop.wav", b"drums"),
+                "Rock",
+            ),
+            audio_file(
+                &test_support::write_file(&keys, "ES_Loop.wav", b"keys"),
+                "Jazz",
+            ),
+            audio_file(
+                &test_support::write_file(&keys, "ES_Pad.wav", b"pad"),
+                "Ambient",
+            ),
+        ];
+        let options = OrganizeOptions {
+            organize_by: "flatten".to_string(),
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 3);
+        let mut names: Vec<String> = fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["ES_Loop.wav", "ES_Loop_1.wav", "ES_Pad.wav"]);
+        assert_eq!(fs::read(output.join("ES_Loop.wav")).unwrap(), b"drums");
+        assert_eq!(fs::read(output.join("ES_Loop_1.wav")).unwrap(), b"keys");
+    }
+
+    #[test]
+    fn flatten_finds_duplicates_in_the_output_root() {
+        let root = test_support::temp_dir("flatten_duplicates");
+        let sources = root.join("sources");
+        let output = root.join("output");
+        fs::create_dir_all(&sources).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        test_support::write_file(&output, "ES_Loop.wav", b"old");
+        let files = [audio_file(
+            &test_support::write_file(&sources, "ES_Loop.wav", b"new"),
+            "Rock",
+        )];
+        let options = OrganizeOptions {
+            organize_by: "flatten".to_string(),
+            ..Default::default()
+        };
+
+        let duplicates = find_duplicates(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        );
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].category, "");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("Rock"), "Rock");
+        assert_eq!(csv_field("Kick, Snare.wav"), "\"Kick, Snare.wav\"");
+        assert_eq!(csv_field("12\" Mix"), "\"12\"\" Mix\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn manifest_lists_every_organized_file() {
+        let root = test_support::temp_dir("manifest");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let kit = test_support::write_file(&sources, "ES_Kick, Snare.wav", b"kit");
+        let pad = test_support::write_file(&sources, "ES_Pad.wav", b"pad");
+        let output = root.join("out");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            write_manifest: true,
+            ..Default::default()
+        };
+
+        organize_files(
+            &[audio_file(&kit, "Rock"), audio_file(&pad, "Ambient")],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        let manifest = fs::read_to_string(output.join(MANIFEST_FILENAME)).unwrap();
+        let kit_destination = output.join("Rock").join("ES_Kick, Snare.wav");
+        let pad_destination = output.join("Ambient").join("ES_Pad.wav");
+        let expected = format!(
+            "source_path,destination_path,category,operation,status\n\"{}\",\"{}\",Rock,copy,success\n{},{},Ambient,copy,success\n",
+            kit,
+            kit_destination.display(),
+            pad,
+            pad_destination.display()
+        );
+        assert_eq!(manifest, expected);
+    }
+
+    #[test]
+    fn identical_files_across_folders_form_one_group() {
+        let _databases = test_support::use_test_databases();
+        cache::init_database().unwrap();
+        let root = test_support::temp_dir("library_duplicates");
+        let mut files = Vec::new();
+        for (folder, contents) in [
+            ("a", "loop"),
+            ("b", "loop"),
+            ("c", "loop"),
+            ("d", "lopp"),
+            ("e", "unique"),
+        ] {
+            let dir = root.join(folder);
+            fs::create_dir_all(&dir).unwrap();
+            let path = test_support::write_file(&dir, "ES_Loop.wav", contents.as_bytes());
+            files.push(audio_file(&path, "House"));
+        }
+        files[1].bitrate_kbps = Some(320);
+
+        let groups = find_all_duplicates(&files);
+
+        assert_eq!(groups.len(), 1);
+        let folders: Vec<&str> = groups[0]
+            .files
+            .iter()
+            .map(|file| file.folder.as_str())
+            .collect();
+        assert_eq!(folders, ["b", "a", "c"]);
+        assert_eq!(groups[0].keep_path, files[1].path);
+    }
+
+    #[test]
+    fn cancelled_duplicate_check_returns_the_duplicates_found_so_far() {
+        let _databases = test_support::use_test_databases();
+        cache::init_database().unwrap();
+        let root = test_support::temp_dir("duplicates_cancel");
+        let sources = root.join("sources");
+        let existing = root.join("out").join("Rock");
+        fs::create_dir_all(&sources).unwrap();
+        fs::create_dir_all(&existing).unwrap();
+        let files: Vec<AudioMetadata> = ["ES_One.wav", "ES_Two.wav", "ES_Three.wav"]
+            .iter()
+            .map(|name| {
+                test_support::write_file(&existing, &format!("Old {}", name), name.as_bytes());
+                audio_file(
+                    &test_support::write_file(&sources, name, name.as_bytes()),
+                    "Rock",
+                )
+            })
+            .collect();
+        let options = OrganizeOptions {
+            compare_content: true,
+            ..Default::default()
+        };
+        let token = CancelToken::default();
+        let output = root.join("out");
+
+        // Cancel while the second file is being checked; the third is never hashed
+        let duplicates = find_duplicates(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &token,
+            |progress| {
+                if progress.done == 1 {
+                    token.cancel();
+                }
+            },
+        );
+
+        let found: Vec<&str> = duplicates
+            .iter()
+            .map(|duplicate| duplicate.source_filename.as_str())
+            .collect();
+        assert_eq!(found, ["ES_One.wav", "ES_Two.wav"]);
+    }
+
+    #[test]
+    fn output_nested_inside_a_source_folder_is_refused() {
+        let sources = test_support::temp_dir("output_nested");
+        let files = [audio_file(
+            &test_support::write_file(&sources, "ES_Riff.wav", b"riff"),
+            "Rock",
+        )];
+        let output = sources.join("Organized");
+
+        let err = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &OrganizeOptions::default(),
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, SmelterError::InvalidInput(_)), "{:?}", err);
+        assert!(!output.exists());
+        assert!(sources.join("ES_Riff.wav").is_file());
+    }
+
+    #[test]
+    fn output_equal_to_the_source_folder_organizes_in_place() {
+        let sources = test_support::temp_dir("output_in_place");
+        let files = [audio_file(
+            &test_support::write_file(&sources, "ES_Riff.wav", b"riff"),
+            "Rock",
+        )];
+
+        let result = organize_files(
+            &files,
+            sources.to_str().unwrap(),
+            &OrganizeOptions::default(),
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert!(sources.join("Rock").join("ES_Riff.wav").is_file());
+        assert!(!sources.join("ES_Riff.wav").exists());
+    }
+
+    #[test]
+    fn output_beside_the_source_folder_is_allowed() {
+        let root = test_support::temp_dir("output_beside");
+        let sources = root.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let files = [audio_file(
+            &test_support::write_file(&sources, "ES_Riff.wav", b"riff"),
+            "Rock",
+        )];
+        let output = root.join("sources-organized");
+
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &OrganizeOptions::default(),
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert!(output.join("Rock").join("ES_Riff.wav").is_file());
+    }
+
+    #[test]
+    fn composed_and_decomposed_names_are_equal() {
+        let composed = "Caf\u{e9}";
+        let decomposed = "Cafe\u{301}";
+        assert_ne!(composed, decomposed);
+
+        assert_eq!(
+            sanitize_folder_name(decomposed),
+            sanitize_folder_name(composed)
+        );
+        assert_eq!(
+            filename_key(decomposed, false),
+            filename_key(composed, false)
+        );
+
+        let files = [
+            audio_file(&format!("/sources/a/ES_{}.wav", composed), "Jazz"),
+            audio_file(&format!("/sources/b/ES_{}.wav", decomposed), "Jazz"),
+        ];
+        let options = OrganizeOptions {
+            case_insensitive_names: false,
+            ..Default::default()
+        };
+        let groups = find_source_duplicates(&files, &options, false);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 2);
+    }
+
+    #[test]
+    fn subfolders_below_the_root_are_kept_under_the_category() {
+        let root = test_support::temp_dir("preserve_structure");
+        let library = root.join("library");
+        let january = library.join("2023").join("January");
+        let elsewhere = root.join("elsewhere");
+        fs::create_dir_all(&january).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        let files = [
+            audio_file(
+                &test_support::write_file(&january, "ES_Riff.wav", b"riff"),
+                "Rock",
+            ),
+            audio_file(
+                &test_support::write_file(&library, "ES_Top.wav", b"top"),
+                "Rock",
+            ),
+            audio_file(
+                &test_support::write_file(&elsewhere, "ES_Out.wav", b"out"),
+                "Rock",
+            ),
+        ];
+        let output = root.join("out");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            preserve_structure_root: Some(library.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 3);
+        let rock = output.join("Rock");
+        assert!(rock
+            .join("2023")
+            .join("January")
+            .join("ES_Riff.wav")
+            .is_file());
+        assert!(rock.join("ES_Top.wav").is_file());
+        assert!(rock.join("ES_Out.wav").is_file());
+    }
+
+    #[test]
+    fn year_month_converts_unix_seconds() {
+        assert_eq!(year_month(0), "1970-01");
+        assert_eq!(year_month(951_782_400), "2000-02");
+        assert_eq!(year_month(1_709_251_199), "2024-02");
+        assert_eq!(year_month(1_709_251_200), "2024-03");
+    }
+
+    #[test]
+    fn date_added_files_land_in_their_month_folder() {
+        let dir = test_support::temp_dir("date_added");
+        let path = dir.join("ES_March.wav");
+        test_support::write_wav(&path, 44_100, 1, 1);
+        // 2024-03-15 00:00:00 UTC
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1_710_460_800, 0))
+            .unwrap();
+        let file =
+            crate::smelter::metadata::read_audio_metadata_full(path.to_str().unwrap()).unwrap();
+        assert_eq!(file.file_modified, Some(1_710_460_800));
+        let output = dir.join("out");
+        let options = OrganizeOptions {
+            organize_by: "date_added".to_string(),
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &[file],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert!(output.join("2024-03").join("ES_March.wav").is_file());
+    }
+
+    #[test]
+    fn txxx_fields_organize_by_their_value() {
+        let dir = test_support::temp_dir("organize_txxx");
+        let mut tagged = audio_file(
+            &test_support::write_file(&dir, "ES_Cut.wav", b"cut"),
+            "Rock",
+        );
+        tagged
+            .custom_fields
+            .insert("Project".to_string(), "AlphaCut".to_string());
+        let untagged = audio_file(
+            &test_support::write_file(&dir, "ES_Other.wav", b"other"),
+            "Rock",
+        );
+        let output = dir.join("out");
+        let options = OrganizeOptions {
+            organize_by: "txxx:Project".to_string(),
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &[tagged, untagged],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 2);
+        assert!(output.join("AlphaCut").join("ES_Cut.wav").is_file());
+        assert!(output.join("Unknown").join("ES_Other.wav").is_file());
+    }
+
+    #[test]
+    fn organizing_twice_skips_files_already_placed() {
+        let dir = test_support::temp_dir("organize_twice");
+        let files = [
+            audio_file(
+                &test_support::write_file(&dir, "ES_One.wav", b"one"),
+                "Rock",
+            ),
+            audio_file(
+                &test_support::write_file(&dir, "ES_Two.wav", b"two"),
+                "Jazz",
+            ),
+        ];
+        let output = dir.join("out");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+        let organize = || {
+            organize_files(
+                &files,
+                output.to_str().unwrap(),
+                &options,
+                &CancelToken::default(),
+                |_| {},
+            )
+            .unwrap()
+        };
+
+        let first = organize();
+        let second = organize();
+
+        assert_eq!(first.success_count, 2);
+        assert_eq!(second.success_count, 0);
+        assert_eq!(second.skipped_count, 2);
+        assert!(!output.join("Rock").join("ES_One_1.wav").exists());
+        assert!(!output.join("Jazz").join("ES_Two_1.wav").exists());
+    }
+
+    #[test]
+    fn a_different_file_with_the_same_name_is_not_skipped() {
+        let dir = test_support::temp_dir("organize_twice_changed");
+        let output = dir.join("out");
+        fs::create_dir_all(output.join("Rock")).unwrap();
+        fs::write(output.join("Rock").join("ES_One.wav"), b"older take").unwrap();
+        let file = audio_file(
+            &test_support::write_file(&dir, "ES_One.wav", b"one"),
+            "Rock",
+        );
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+
+        let result = organize_files(
+            &[file],
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert_eq!(result.skipped_count, 0);
+        assert!(output.join("Rock").join("ES_One_1.wav").is_file());
+    }
+
+    #[test]
+    fn organizing_stops_early_when_the_output_folder_disappears() {
+        let dir = test_support::temp_dir("destination_gone");
+        let files: Vec<AudioMetadata> = (0..5)
+            .map(|index| {
+                let name = format!("ES_{}.wav", index);
+                audio_file(
+                    &test_support::write_file(&dir, &name, name.as_bytes()),
+                    "Rock",
+                )
+            })
+            .collect();
+        let output = dir.join("out");
+        let options = OrganizeOptions {
+            operation: "copy".to_string(),
+            ..Default::default()
+        };
+
+        // After two files, the output folder is replaced by a plain file, like an unmounted
+        // drive whose mount point can no longer hold folders
+        let result = organize_files(
+            &files,
+            output.to_str().unwrap(),
+            &options,
+            &CancelToken::default(),
+            |progress| {
+                if progress.done == 2 {
+                    fs::remove_dir_all(&output).unwrap();
+                    fs::write(&output, b"").unwrap();
+                }
+            },
+        )
+        .unwrap();
+
+        assert!(result.destination_unavailable);
+        assert_eq!(result.success_count, 2);
+        assert_eq!(result.error_count, 0);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].starts_with("Destination unavailable"));
+        assert!(result.errors[0].contains("3 of 5 files left"));
+    }
+
+    /// Preview names that will be placed, and the names organizing planned, in file order
+    fn preview_and_planned_names(
+        files: &[AudioMetadata],
+        output: &Path,
+        options: &OrganizeOptions,
+    ) -> (Vec<PreviewEntry>, Vec<String>, OrganizeResult) {
+        let output = output.to_str().unwrap();
+        let preview = preview_final_names(files, output, options)
+            .remove("Rock")
+            .unwrap();
+        let result =
+            organize_files(files, output, options, &CancelToken::default(), |_| {}).unwrap();
+        let planned = result
+            .planned
+            .iter()
+            .map(|planned| {
+                Path::new(&planned.destination_path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        (preview, planned, result)
+    }
+
+    #[test]
+    fn preview_skips_the_second_drums_like_organize_does() {
+        let root = test_support::temp_dir("preview_skip");
+        let files = two_drums(&root);
+        let options = dry_run_options(ConflictStrategy::Skip);
+
+        let (preview, planned, result) =
+            preview_and_planned_names(&files, &root.join("out"), &options);
+
+        assert_eq!(preview.len(), 2);
+        assert_eq!(preview[0].final_filename.as_deref(), Some("ES_Drums.wav"));
+        assert!(!preview[0].skipped);
+        assert!(preview[1].skipped);
+        assert_eq!(result.skipped_count, 1);
+        assert_eq!(planned, vec!["ES_Drums.wav".to_string()]);
+    }
+
+    #[test]
+    fn preview_renames_the_second_drums_like_organize_does() {
+        let root = test_support::temp_dir("preview_rename");
+        let files = two_drums(&root);
+        let options = dry_run_options(ConflictStrategy::Rename);
+
+        let (preview, planned, result) =
+            preview_and_planned_names(&files, &root.join("out"), &options);
+
+        let previewed: Vec<String> = preview
+            .iter()
+            .filter(|entry| !entry.skipped)
+            .filter_map(|entry| entry.final_filename.clone())
+            .collect();
+        assert_eq!(
+            previewed,
+            vec!["ES_Drums.wav".to_string(), "ES_Drums_1.wav".to_string()]
+        );
+        assert_eq!(previewed, planned);
+        assert_eq!(result.skipped_count, 0);
+    }
+
+    #[test]
+    fn artists_sort_into_letter_folders() {
+        let options = OrganizeOptions {
+            organize_by: "alpha".to_string(),
+            ..Default::default()
+        };
+        let cases = [
+            (Some("ABBA"), "A"),
+            (Some("99 Luftballons"), "#"),
+            (Some("élan"), "E"),
+            (Some("  the xx"), "T"),
+            // No artist or title: the filename decides
+            (None, "E"),
+        ];
+
+        for (artist, folder) in cases {
+            let file = AudioMetadata {
+                artist: artist.map(str::to_string),
+                ..audio_file("/sources/ES_Song.wav", "Pop")
+            };
+            assert_eq!(
+                get_file_category(&file, &options),
+                folder,
+                "artist {:?}",
+                artist
+            );
+        }
+    }
+
+    #[test]
+    fn fuzzy_mode_groups_spacing_and_punctuation_variants() {
+        assert_eq!(fuzzy_filename_key("ES_Epic  Hit.WAV"), "es epic hit.wav");
+
+        let files = [
+            audio_file("/sources/a/ES_Epic Hit.wav", "Rock"),
+            audio_file("/sources/b/ES_epic_hit.wav", "Rock"),
+            audio_file("/sources/c/ES_Epic - Hit .wav", "Rock"),
+            audio_file("/sources/d/ES_Epic Hits.wav", "Rock"),
+        ];
+        let options = OrganizeOptions::default();
+
+        let groups = find_source_duplicates(&files, &options, true);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 3);
+        assert_eq!(groups[0].normalized_key.as_deref(), Some("es epic hit.wav"));
+        assert_eq!(groups[0].filename, "ES_Epic Hit.wav");
+
+        // Without fuzzy mode the names are all different
+        assert!(find_source_duplicates(&files, &options, false).is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+// Undo support for the most recent organize run
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::data_dir;
+
+use super::organize::{format_fs_error, move_file};
+use super::{OrganizeResult, SmelterError};
+
+/// A file placed by an organize run, with the destination's state right after it was placed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OrganizedFile {
+    source: String,
+    destination: String,
+    modified: i64,
+    size: u64,
+}
+
+/// The most recent organize run that can be undone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OrganizeHistory {
+    operation: String,
+    files: Vec<OrganizedFile>,
+}
+
+/// Get the path of the undo history file in the app data directory
+fn get_history_path() -> PathBuf {
+    let mut path = data_dir::resolve(dirs_next::data_local_dir())
+        .unwrap_or_else(|| PathBuf::from(".").join(data_dir::APP_DIR_NAME));
+    std::fs::create_dir_all(&path).ok();
+    path.push("last_organize.json");
+    path
+}
+
+/// Get a file's modification time (unix seconds) and size, or None if it doesn't exist
+fn file_stat(path: &Path) -> Option<(i64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    Some((modified, meta.len()))
+}
+
+/// Remember an organize run so it can be undone (replaces the previous run)
+/// `moved` holds (source_path, destination_path) pairs as returned in OrganizeResult
+pub fn record_organize(operation: &str, moved: &[(String, String)]) -> Result<(), SmelterError> {
+    if moved.is_empty() {
+        return Ok(());
+    }
+
+    let files = moved
+        .iter()
+        .map(|(source, destination)| {
+            let (modified, size) = file_stat(Path::new(destination)).unwrap_or((0, 0));
+            OrganizedFile {
+                source: source.clone(),
+                destination: destination.clone(),
+                modified,
+                size,
+            }
+        })
+        .collect();
+
+    let history = OrganizeHistory {
+        operation: operation.to_string(),
+        files,
+    };
+    let json = serde_json::to_string(&history)
+        .map_err(|e| SmelterError::Io(format!("Failed to save undo history: {}", e)))?;
+    let history_path = get_history_path();
+    fs::write(&history_path, json).map_err(|e| {
+        SmelterError::from_io(&e, &history_path.to_string_lossy(), "save undo history")
+    })
+}
+
+/// Undo the most recent organize run
+/// Moved files go back to their original location; copies and links are deleted.
+/// Files modified at the destination since organizing are left alone and counted as skipped.
+/// Files that couldn't be restored stay in the history so undo can be retried
+pub fn undo_last_organize() -> Result<OrganizeResult, SmelterError> {
+    let history_path = get_history_path();
+    let json = fs::read_to_string(&history_path).map_err(|_| {
+        SmelterError::NotFound("There is no organize operation to undo".to_string())
+    })?;
+    let history: OrganizeHistory = serde_json::from_str(&json)
+        .map_err(|e| SmelterError::Io(format!("Failed to read undo history: {}", e)))?;
+
+    let mut success_count = 0u32;
+    let mut error_count = 0u32;
+    let mut skipped_count = 0u32;
+    let mut errors = Vec::new();
+    let mut moved = Vec::new();
+    let mut remaining = Vec::new();
+
+    // Newest first, mirroring how atomic rollback reverses moves
+    for file in history.files.into_iter().rev() {
+        let destination = Path::new(&file.destination);
+
+        match file_stat(destination) {
+            None => {
+                errors.push(format!("File not found: '{}' may have been moved or deleted.", file.destination));
+                error_count += 1;
+                continue;
+            }
+            Some(stat) if stat != (file.modified, file.size) => {
+                errors.push(format!("Skipped '{}': it was modified after organizing", file.destination));
+                skipped_count += 1;
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        let result = if history.operation == "move" {
+            if let Some(parent) = Path::new(&file.source).parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            move_file(destination, Path::new(&file.source))
+        } else {
+            fs::remove_file(destination)
+        };
+
+        match result {
+            Ok(_) => {
+                success_count += 1;
+                moved.push((file.destination.clone(), file.source.clone()));
+            }
+            Err(e) => {
+                errors.push(format_fs_error(&e, &file.destination, "restore"));
+                error_count += 1;
+                remaining.push(file);
+            }
+        }
+    }
+
+    if remaining.is_empty() {
+        let _ = fs::remove_file(&history_path);
+    } else {
+        remaining.reverse();
+        let history = OrganizeHistory {
+            operation: history.operation,
+            files: remaining,
+        };
+        if let Ok(json) = serde_json::to_string(&history) {
+            let _ = fs::write(&history_path, json);
+        }
+    }
+
+    Ok(OrganizeResult {
+        success_count,
+        error_count,
+        skipped_count,
+        errors,
+        cancelled: false,
+        destination_unavailable: false,
+        planned: Vec::new(),
+        moved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn undoing_a_move_restores_the_original_layout() {
+        let _databases = test_support::use_test_databases();
+        let dir = test_support::temp_dir("undo_move");
+        let source = test_support::write_file(&dir, "ES_Kick.wav", b"kick");
+        fs::create_dir_all(dir.join("Drums")).unwrap();
+        let destination = dir
+            .join("Drums")
+            .join("ES_Kick.wav")
+            .to_string_lossy()
+            .to_string();
+        fs::rename(&source, &destination).unwrap();
+        record_organize("move", &[(source.clone(), destination.clone())]).unwrap();
+
+        let result = undo_last_organize().unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert_eq!(result.moved, vec![(destination.clone(), source.clone())]);
+        assert_eq!(fs::read(&source).unwrap(), b"kick");
+        assert!(!Path::new(&destination).exists());
+        assert!(
+            undo_last_organize().is_err(),
+            "history is cleared after a full undo"
+        );
+    }
+
+    #[test]
+    fn undoing_a_copy_deletes_the_copies() {
+        let _databases = test_support::use_test_databases();
+        let dir = test_support::temp_dir("undo_copy");
+        let source = test_support::write_file(&dir, "ES_Kick.wav", b"kick");
+        let copy = test_support::write_file(&dir, "copy.wav", b"kick");
+        record_organize("copy", &[(source.clone(), copy.clone())]).unwrap();
+
+        let result = undo_last_organize().unwrap();
+
+        assert_eq!(result.success_count, 1);
+        assert!(Path::new(&source).exists());
+        assert!(!Path::new(&copy).exists());
+    }
+
+    #[test]
+    fn files_modified_after_organizing_are_skipped() {
+        let _databases = test_support::use_test_databases();
+        let dir = test_support::temp_dir("undo_modified");
+        let source = dir.join("ES_Kick.wav").to_string_lossy().to_string();
+        let destination = test_support::write_file(&dir, "organized.wav", b"kick");
+        record_organize("move", &[(source.clone(), destination.clone())]).unwrap();
+        // e.g. retagged with set_field_bulk after organizing
+        fs::write(&destination, b"kick with new tags").unwrap();
+
+        let result = undo_last_organize().unwrap();
+
+        assert_eq!(result.success_count, 0);
+        assert_eq!(result.skipped_count, 1);
+        assert!(Path::new(&destination).exists());
+        assert!(!Path::new(&source).exists());
+    }
+}
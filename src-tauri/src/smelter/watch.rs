@@ -0,0 +1,144 @@
+// Live watching of folders for added, changed and removed audio files
+use notify_debouncer_mini::notify::{self, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use super::metadata::{is_supported_audio_file, read_audio_metadata_full};
+use super::{cache, AudioMetadata, SmelterError};
+
+/// How long to wait for a burst of filesystem events to settle (e.g. a file still being written)
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+// Active watchers by watched folder (dropping a debouncer stops its watcher)
+lazy_static::lazy_static! {
+    static ref WATCHERS: Mutex<HashMap<String, Debouncer<RecommendedWatcher>>> = Mutex::new(HashMap::new());
+}
+
+/// A change to an audio file inside a watched folder
+pub enum WatchEvent {
+    /// A file was created or modified (with freshly read metadata)
+    Added(AudioMetadata),
+    /// A file was deleted or moved away
+    Removed(String),
+}
+
+/// Start watching a folder recursively, calling `on_event` for every changed audio file
+/// Watching a folder that is already watched replaces the previous watcher
+pub fn watch_directory(
+    path: &str,
+    on_event: impl Fn(WatchEvent) + Send + 'static,
+) -> Result<(), SmelterError> {
+    let mut debouncer = new_debouncer(DEBOUNCE_DELAY, move |result: DebounceEventResult| {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Watch error: {:?}", e);
+                return;
+            }
+        };
+
+        for event in events {
+            if !is_supported_audio_file(&event.path) {
+                continue;
+            }
+            let Some(path_str) = event.path.to_str() else {
+                continue;
+            };
+
+            // The debounced event doesn't say what happened, so check whether the file is still there
+            if event.path.exists() {
+                match read_with_cache(path_str) {
+                    Ok(metadata) => on_event(WatchEvent::Added(metadata)),
+                    Err(e) => eprintln!("Error reading {}: {}", path_str, e),
+                }
+            } else {
+                on_event(WatchEvent::Removed(path_str.to_string()));
+            }
+        }
+    })
+    .map_err(|e| SmelterError::Io(format!("Failed to create watcher: {}", e)))?;
+
+    debouncer
+        .watcher()
+        .watch(Path::new(path), RecursiveMode::Recursive)
+        .map_err(|e| match &e.kind {
+            notify::ErrorKind::Io(io) => SmelterError::from_io(io, path, "watch"),
+            notify::ErrorKind::PathNotFound => SmelterError::NotFound(format!(
+                "File not found: '{}' may have been moved or deleted.",
+                path
+            )),
+            _ => SmelterError::Io(format!("Failed to watch '{}': {}", path, e)),
+        })?;
+
+    WATCHERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(path.to_string(), debouncer);
+    Ok(())
+}
+
+/// Stop watching a folder
+/// Returns false if the folder wasn't being watched
+pub fn stop_watching(path: &str) -> bool {
+    WATCHERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(path)
+        .is_some()
+}
+
+/// Read metadata for a changed file, serving it from the cache if the file is unchanged
+fn read_with_cache(path: &str) -> Result<AudioMetadata, SmelterError> {
+    if let Ok(Some(cached)) = cache::get_cached_metadata(path, false) {
+        return Ok(cached);
+    }
+
+    let metadata = read_audio_metadata_full(path)?;
+    let _ = cache::cache_metadata(&metadata);
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::sync::mpsc;
+
+    /// Generous upper bound for the watcher to report a change (debounce included)
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn added_and_removed_audio_files_are_reported() {
+        let _databases = test_support::use_test_databases();
+        cache::init_database().unwrap();
+        let dir = test_support::temp_dir("watch-events");
+        let watched = dir.to_string_lossy().to_string();
+
+        let (sender, events) = mpsc::channel();
+        watch_directory(&watched, move |event| {
+            let _ = sender.send(event);
+        })
+        .unwrap();
+
+        // Non-audio files are ignored, so the first event is the wav
+        test_support::write_file(&dir, "notes.txt", b"not audio");
+        let wav = dir.join("kick.wav");
+        test_support::write_wav(&wav, 44100, 1, 1);
+        match events.recv_timeout(EVENT_TIMEOUT).unwrap() {
+            WatchEvent::Added(metadata) => assert_eq!(metadata.filename, "kick.wav"),
+            WatchEvent::Removed(path) => panic!("unexpected removal of {}", path),
+        }
+
+        std::fs::remove_file(&wav).unwrap();
+        match events.recv_timeout(EVENT_TIMEOUT).unwrap() {
+            WatchEvent::Removed(path) => assert!(path.ends_with("kick.wav")),
+            WatchEvent::Added(metadata) => panic!("unexpected add of {}", metadata.path),
+        }
+
+        assert!(stop_watching(&watched));
+        assert!(!stop_watching(&watched));
+    }
+}
@@ -1,30 +1,78 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use lazy_static::lazy_static;
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::data_dir;
+use crate::smelter::SmelterError;
+
+/// Events that failed to send this many times are no longer returned as pending
+const MAX_SEND_ATTEMPTS: u32 = 10;
+
+/// Pending events returned per page unless the caller asks for another size
+pub const DEFAULT_PENDING_LIMIT: u32 = 100;
+
+/// Largest page of pending events returned at once
+const MAX_PENDING_LIMIT: u32 = 1000;
+
+/// Ids bound per UPDATE statement (stays under SQLite's 999-variable limit on older builds)
+const IDS_PER_STATEMENT: usize = 500;
+
+/// Payloads larger than this (in bytes of JSON) are stored gzip-compressed
+const COMPRESS_THRESHOLD: usize = 1024;
 
 lazy_static! {
     static ref QUEUE_DB: Mutex<Option<Connection>> = Mutex::new(None);
 }
 
+/// Wrap a database, serialization or compression failure in the queue
+fn telemetry_error(e: impl std::fmt::Display) -> SmelterError {
+    SmelterError::Telemetry(e.to_string())
+}
+
+/// The open queue connection from a locked guard
+fn connection(db: &Option<Connection>) -> Result<&Connection, SmelterError> {
+    db.as_ref()
+        .ok_or_else(|| SmelterError::Telemetry("Database not initialized".to_string()))
+}
+
+/// Lock the queue database, recovering the guard if a thread panicked while holding it
+/// The connection itself stays usable (SQLite rolls back an unfinished transaction), so one
+/// panic shouldn't disable telemetry for the rest of the session
+fn lock_queue() -> MutexGuard<'static, Option<Connection>> {
+    QUEUE_DB.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether events are queued at all (persisted in the settings table)
+static TELEMETRY_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Path of the telemetry database (None if the platform has no data directory)
+pub fn get_db_path() -> Option<std::path::PathBuf> {
+    data_dir::resolve(dirs_next::data_dir()).map(|dir| dir.join("telemetry.db"))
+}
+
 /// Initialize the telemetry database
-pub fn init_database() -> Result<(), String> {
-    let mut db = QUEUE_DB.lock().map_err(|e| e.to_string())?;
+pub fn init_database() -> Result<(), SmelterError> {
+    let mut db = lock_queue();
     if db.is_some() {
         return Ok(());
     }
 
-    let db_path = dirs_next::data_dir()
-        .ok_or("Could not find data directory")?
-        .join("com.editorworkshop.app")
-        .join("telemetry.db");
+    let db_path = get_db_path()
+        .ok_or_else(|| SmelterError::Telemetry("Could not find data directory".to_string()))?;
 
     // Ensure parent directory exists
     if let Some(parent) = db_path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(parent)
+            .map_err(|e| SmelterError::from_io(&e, &parent.to_string_lossy(), "create folder"))?;
     }
 
-    let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
+    let conn = data_dir::open_database(&db_path).map_err(SmelterError::Telemetry)?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS event_queue (
@@ -32,63 +80,211 @@ pub fn init_database() -> Result<(), String> {
             event_type TEXT NOT NULL,
             payload TEXT NOT NULL,
             created_at INTEGER NOT NULL,
-            sent INTEGER DEFAULT 0
+            sent INTEGER DEFAULT 0,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            compressed INTEGER NOT NULL DEFAULT 0,
+            priority INTEGER NOT NULL DEFAULT 0,
+            payload_hash TEXT,
+            count INTEGER NOT NULL DEFAULT 1
         )",
         [],
     )
-    .map_err(|e| e.to_string())?;
+    .map_err(telemetry_error)?;
+
+    // Add attempts column if it doesn't exist (migration)
+    let _ = conn.execute(
+        "ALTER TABLE event_queue ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Add compressed flag if it doesn't exist (migration, legacy rows are plain JSON text)
+    let _ = conn.execute(
+        "ALTER TABLE event_queue ADD COLUMN compressed INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Add priority column if it doesn't exist (migration)
+    let _ = conn.execute(
+        "ALTER TABLE event_queue ADD COLUMN priority INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Add dedup columns if they don't exist (migration, legacy rows are never merged into)
+    let _ = conn.execute("ALTER TABLE event_queue ADD COLUMN payload_hash TEXT", []);
+    let _ = conn.execute(
+        "ALTER TABLE event_queue ADD COLUMN count INTEGER NOT NULL DEFAULT 1",
+        [],
+    );
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(telemetry_error)?;
+
+    // Restore the user's opt-out choice
+    let enabled: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'telemetry_enabled'",
+            [],
+            |row| row.get(0),
+        )
+        .ok();
+    TELEMETRY_ENABLED.store(enabled.as_deref() != Some("false"), Ordering::Relaxed);
 
     *db = Some(conn);
     Ok(())
 }
 
+/// Enable or disable telemetry (persisted across restarts)
+/// Disabling also drops any events that haven't been sent yet
+pub fn set_enabled(enabled: bool) -> Result<(), SmelterError> {
+    init_database()?;
+
+    let db = lock_queue();
+    let conn = connection(&db)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('telemetry_enabled', ?1)",
+        params![enabled.to_string()],
+    )
+    .map_err(telemetry_error)?;
+
+    if !enabled {
+        conn.execute("DELETE FROM event_queue WHERE sent = 0", [])
+            .map_err(telemetry_error)?;
+    }
+
+    TELEMETRY_ENABLED.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct QueuedEvent {
     pub event_type: String,
     pub payload: serde_json::Value,
+    /// Number of failed send attempts so far (lets the frontend back off)
+    #[serde(default)]
+    pub attempts: u32,
+    /// Higher priority events (e.g. crash reports) are sent first; 0 is the default
+    #[serde(default)]
+    pub priority: i32,
+    /// How many identical events were collapsed into this one by deduplication
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+fn default_count() -> u32 {
+    1
 }
 
-/// Queue an event for later sending
-pub fn queue_event(event: &QueuedEvent) -> Result<(), String> {
+/// Queue an event for later sending (silently dropped if telemetry is disabled)
+/// With a `dedup_window_secs` above 0, an event identical (same type, payload and priority) to
+/// an unsent one queued within that many seconds only increments the earlier event's `count`
+pub fn queue_event(event: &QueuedEvent, dedup_window_secs: u32) -> Result<(), SmelterError> {
     init_database()?;
 
-    let db = QUEUE_DB.lock().map_err(|e| e.to_string())?;
-    let conn = db.as_ref().ok_or("Database not initialized")?;
+    if !TELEMETRY_ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let db = lock_queue();
+    let conn = connection(&db)?;
+
+    // Never store paths that reveal the user's name
+    let mut payload = event.payload.clone();
+    scrub_paths(&mut payload);
+    let json = serde_json::to_string(&payload).map_err(telemetry_error)?;
+    let payload_hash = blake3::hash(json.as_bytes()).to_hex().to_string();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(telemetry_error)?
+        .as_secs() as i64;
+
+    if dedup_window_secs > 0 {
+        let merged = conn
+            .execute(
+                "UPDATE event_queue SET count = count + 1 WHERE id = (
+                    SELECT id FROM event_queue
+                    WHERE sent = 0 AND event_type = ?1 AND payload_hash = ?2 AND priority = ?3
+                        AND created_at >= ?4
+                    ORDER BY id DESC LIMIT 1
+                )",
+                params![
+                    event.event_type,
+                    payload_hash,
+                    event.priority,
+                    now - dedup_window_secs as i64
+                ],
+            )
+            .map_err(telemetry_error)?;
+        if merged > 0 {
+            return Ok(());
+        }
+    }
+
+    // Large payloads are stored as a gzip BLOB, small ones as plain text
+    let (payload, compressed) = if json.len() > COMPRESS_THRESHOLD {
+        (rusqlite::types::Value::Blob(compress(&json)?), true)
+    } else {
+        (rusqlite::types::Value::Text(json), false)
+    };
 
     conn.execute(
-        "INSERT INTO event_queue (event_type, payload, created_at) VALUES (?1, ?2, ?3)",
+        "INSERT INTO event_queue (event_type, payload, compressed, priority, payload_hash, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
             event.event_type,
-            serde_json::to_string(&event.payload).map_err(|e| e.to_string())?,
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| e.to_string())?
-                .as_secs() as i64
+            payload,
+            compressed,
+            event.priority,
+            payload_hash,
+            now
         ],
     )
-    .map_err(|e| e.to_string())?;
+    .map_err(telemetry_error)?;
 
     Ok(())
 }
 
-/// Get pending events that haven't been sent
-pub fn get_pending_events() -> Result<Vec<(i64, QueuedEvent)>, String> {
+/// Get pending events that haven't been sent, highest priority first (oldest first within a priority)
+/// Events that already failed MAX_SEND_ATTEMPTS times are skipped so they can't block the queue
+/// Returns up to `limit` events (clamped to 1..=MAX_PENDING_LIMIT) after skipping `offset`
+pub fn get_pending_events(
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<(i64, QueuedEvent)>, SmelterError> {
     init_database()?;
 
-    let db = QUEUE_DB.lock().map_err(|e| e.to_string())?;
-    let conn = db.as_ref().ok_or("Database not initialized")?;
+    let limit = limit.clamp(1, MAX_PENDING_LIMIT);
+
+    let db = lock_queue();
+    let conn = connection(&db)?;
 
     let mut stmt = conn
         .prepare(
-            "SELECT id, event_type, payload FROM event_queue WHERE sent = 0 ORDER BY created_at LIMIT 100",
+            "SELECT id, event_type, payload, attempts, compressed, priority, count FROM event_queue
+             WHERE sent = 0 AND attempts < ?1
+             ORDER BY priority DESC, created_at ASC, id ASC LIMIT ?2 OFFSET ?3",
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(telemetry_error)?;
 
     let events = stmt
-        .query_map([], |row| {
+        .query_map(params![MAX_SEND_ATTEMPTS, limit, offset], |row| {
             let id: i64 = row.get(0)?;
             let event_type: String = row.get(1)?;
-            let payload_str: String = row.get(2)?;
+            let attempts: u32 = row.get(3)?;
+            let compressed: bool = row.get(4)?;
+            let priority: i32 = row.get(5)?;
+            let count: u32 = row.get(6)?;
+            let payload_str = if compressed {
+                decompress(&row.get::<_, Vec<u8>>(2)?).unwrap_or_default()
+            } else {
+                row.get::<_, String>(2)?
+            };
             let payload: serde_json::Value =
                 serde_json::from_str(&payload_str).unwrap_or(serde_json::Value::Null);
 
@@ -97,30 +293,167 @@ pub fn get_pending_events() -> Result<Vec<(i64, QueuedEvent)>, String> {
                 QueuedEvent {
                     event_type,
                     payload,
+                    attempts,
+                    priority,
+                    count,
                 },
             ))
         })
-        .map_err(|e| e.to_string())?
+        .map_err(telemetry_error)?
         .filter_map(|r| r.ok())
         .collect();
 
     Ok(events)
 }
 
+/// Count events that haven't been sent yet
+/// Matches get_pending_events: events past MAX_SEND_ATTEMPTS aren't counted
+pub fn count_pending_events() -> Result<u32, SmelterError> {
+    init_database()?;
+
+    let db = lock_queue();
+    let conn = connection(&db)?;
+
+    conn.query_row(
+        "SELECT COUNT(*) FROM event_queue WHERE sent = 0 AND attempts < ?1",
+        params![MAX_SEND_ATTEMPTS],
+        |row| row.get(0),
+    )
+    .map_err(telemetry_error)
+}
+
+/// Placeholder for the user-identifying part of a path
+const HOME_PLACEHOLDER: &str = "<HOME>";
+
+/// Home directory prefixes whose next path component is a user name
+const HOME_MARKERS: &[&str] = &["/Users/", "/home/", ":\\Users\\", ":/Users/"];
+
+/// Replace home directories in every string of a payload (recursing into objects and arrays)
+fn scrub_paths(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => *text = scrub_home_paths(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(scrub_paths),
+        serde_json::Value::Object(map) => map.values_mut().for_each(scrub_paths),
+        _ => {}
+    }
+}
+
+/// Replace home directories like `/Users/jane` or `C:\Users\jane` in a string with <HOME>
+/// Conservative: only rewrites the current home dir and paths that start at a word boundary
+fn scrub_home_paths(text: &str) -> String {
+    let mut result = text.to_string();
+
+    if let Some(home) = dirs_next::home_dir() {
+        let home = home.to_string_lossy();
+        if home.len() > 1 {
+            result = result.replace(home.as_ref(), HOME_PLACEHOLDER);
+        }
+    }
+
+    for marker in HOME_MARKERS {
+        let separator = if marker.ends_with('\\') { '\\' } else { '/' };
+        let mut search_from = 0;
+
+        while let Some(offset) = result[search_from..].find(marker) {
+            let marker_start = search_from + offset;
+            let name_start = marker_start + marker.len();
+            search_from = name_start;
+
+            // Drive-letter markers start at the letter before the colon,
+            // Unix markers must begin the string or follow a delimiter (not part of a URL)
+            let start = if marker.starts_with(':') {
+                match marker_start.checked_sub(1) {
+                    Some(s) if result.as_bytes()[s].is_ascii_alphabetic() => s,
+                    _ => continue,
+                }
+            } else {
+                match result[..marker_start].chars().next_back() {
+                    None => marker_start,
+                    Some(c) if c.is_whitespace() || matches!(c, '"' | '\'' | '(' | '=') => marker_start,
+                    Some(_) => continue,
+                }
+            };
+
+            let name_end = result[name_start..]
+                .find(|c: char| c == separator || c == '"' || c.is_whitespace())
+                .map(|i| name_start + i)
+                .unwrap_or(result.len());
+            if name_end == name_start {
+                continue;
+            }
+
+            result.replace_range(start..name_end, HOME_PLACEHOLDER);
+            search_from = start + HOME_PLACEHOLDER.len();
+        }
+    }
+
+    result
+}
+
+/// Gzip-compress a JSON payload
+fn compress(json: &str) -> Result<Vec<u8>, SmelterError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(json.as_bytes())
+        .map_err(telemetry_error)?;
+    encoder.finish().map_err(telemetry_error)
+}
+
+/// Decompress a gzip-compressed JSON payload
+fn decompress(bytes: &[u8]) -> Result<String, SmelterError> {
+    let mut json = String::new();
+    GzDecoder::new(bytes)
+        .read_to_string(&mut json)
+        .map_err(telemetry_error)?;
+    Ok(json)
+}
+
 /// Mark events as sent
-pub fn mark_sent(ids: &[i64]) -> Result<(), String> {
+/// One UPDATE per IDS_PER_STATEMENT ids, all in a single transaction
+pub fn mark_sent(ids: &[i64]) -> Result<(), SmelterError> {
     if ids.is_empty() {
         return Ok(());
     }
 
     init_database()?;
 
-    let db = QUEUE_DB.lock().map_err(|e| e.to_string())?;
-    let conn = db.as_ref().ok_or("Database not initialized")?;
+    let db = lock_queue();
+    let conn = connection(&db)?;
+
+    let tx = conn.unchecked_transaction().map_err(telemetry_error)?;
+    for chunk in ids.chunks(IDS_PER_STATEMENT) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        tx.execute(
+            &format!(
+                "UPDATE event_queue SET sent = 1 WHERE id IN ({})",
+                placeholders
+            ),
+            rusqlite::params_from_iter(chunk),
+        )
+        .map_err(telemetry_error)?;
+    }
+    tx.commit().map_err(telemetry_error)?;
+
+    Ok(())
+}
+
+/// Record a failed send attempt for events
+pub fn record_send_failure(ids: &[i64]) -> Result<(), SmelterError> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    init_database()?;
+
+    let db = lock_queue();
+    let conn = connection(&db)?;
 
     for id in ids {
-        conn.execute("UPDATE event_queue SET sent = 1 WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE event_queue SET attempts = attempts + 1 WHERE id = ?1",
+            params![id],
+        )
+        .map_err(telemetry_error)?;
     }
 
     Ok(())
@@ -129,15 +462,15 @@ pub fn mark_sent(ids: &[i64]) -> Result<(), String> {
 /// Clean up old sent events (older than 7 days)
 /// Note: Called periodically to prevent database bloat
 #[allow(dead_code)]
-pub fn cleanup_old_events() -> Result<u32, String> {
+pub fn cleanup_old_events() -> Result<u32, SmelterError> {
     init_database()?;
 
-    let db = QUEUE_DB.lock().map_err(|e| e.to_string())?;
-    let conn = db.as_ref().ok_or("Database not initialized")?;
+    let db = lock_queue();
+    let conn = connection(&db)?;
 
     let cutoff = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
+        .map_err(telemetry_error)?
         .as_secs() as i64
         - (7 * 24 * 60 * 60); // 7 days ago
 
@@ -146,7 +479,319 @@ pub fn cleanup_old_events() -> Result<u32, String> {
             "DELETE FROM event_queue WHERE sent = 1 AND created_at < ?1",
             params![cutoff],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(telemetry_error)?;
 
     Ok(deleted as u32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn event(event_type: &str) -> QueuedEvent {
+        QueuedEvent {
+            event_type: event_type.to_string(),
+            payload: serde_json::json!({ "screen": "smelter" }),
+            attempts: 0,
+            priority: 0,
+            count: 1,
+        }
+    }
+
+    /// Turns telemetry back on when a test that disabled it finishes
+    struct Reenable;
+    impl Drop for Reenable {
+        fn drop(&mut self) {
+            let _ = set_enabled(true);
+        }
+    }
+
+    /// Drop every queued event
+    fn empty_queue() {
+        init_database().unwrap();
+        let db = lock_queue();
+        db.as_ref()
+            .unwrap()
+            .execute("DELETE FROM event_queue", [])
+            .unwrap();
+    }
+
+    #[test]
+    fn events_past_the_retry_cap_are_no_longer_pending() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+        queue_event(&event("failing"), 0).unwrap();
+        queue_event(&event("healthy"), 0).unwrap();
+        let find = |event_type: &str| {
+            get_pending_events(DEFAULT_PENDING_LIMIT, 0)
+                .unwrap()
+                .into_iter()
+                .find(|(_, event)| event.event_type == event_type)
+        };
+        let failing = find("failing").unwrap().0;
+
+        for attempt in 1..MAX_SEND_ATTEMPTS {
+            record_send_failure(&[failing]).unwrap();
+            assert_eq!(find("failing").unwrap().1.attempts, attempt);
+        }
+        record_send_failure(&[failing]).unwrap();
+
+        let pending = get_pending_events(DEFAULT_PENDING_LIMIT, 0).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.event_type, "healthy");
+    }
+
+    #[test]
+    fn large_payloads_round_trip_through_compression() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+        let files: Vec<String> = (0..200).map(|i| format!("ES_Track_{}.wav", i)).collect();
+        let large = QueuedEvent {
+            payload: serde_json::json!({ "files": files }),
+            ..event("scan_summary")
+        };
+        queue_event(&large, 0).unwrap();
+        {
+            // A legacy row written before compression existed
+            let db = lock_queue();
+            db.as_ref()
+                .unwrap()
+                .execute(
+                    "INSERT INTO event_queue (event_type, payload, created_at) VALUES ('legacy', '{\"old\":true}', 0)",
+                    [],
+                )
+                .unwrap();
+            let compressed: bool = db
+                .as_ref()
+                .unwrap()
+                .query_row(
+                    "SELECT compressed FROM event_queue WHERE event_type = 'scan_summary'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(compressed);
+        }
+
+        let pending = get_pending_events(DEFAULT_PENDING_LIMIT, 0).unwrap();
+        let payload = |event_type: &str| {
+            pending
+                .iter()
+                .find(|(_, event)| event.event_type == event_type)
+                .map(|(_, event)| event.payload.clone())
+                .unwrap()
+        };
+        assert_eq!(payload("scan_summary"), large.payload);
+        assert_eq!(payload("legacy"), serde_json::json!({ "old": true }));
+    }
+
+    #[test]
+    fn higher_priority_events_drain_first() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+        for (event_type, priority) in [("click", 0), ("crash", 10), ("scroll", 0), ("error", 5)] {
+            queue_event(
+                &QueuedEvent {
+                    priority,
+                    ..event(event_type)
+                },
+                0,
+            )
+            .unwrap();
+        }
+
+        let order: Vec<String> = get_pending_events(DEFAULT_PENDING_LIMIT, 0)
+            .unwrap()
+            .into_iter()
+            .map(|(_, event)| event.event_type)
+            .collect();
+
+        assert_eq!(order, ["crash", "error", "click", "scroll"]);
+    }
+
+    #[test]
+    fn opting_out_drops_new_events() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+        let _reenable = Reenable;
+        set_enabled(false).unwrap();
+
+        queue_event(&event("click"), 0).unwrap();
+
+        assert!(get_pending_events(DEFAULT_PENDING_LIMIT, 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn home_paths_are_scrubbed_from_nested_payloads() {
+        let mut payload = serde_json::json!({
+            "error": "Cannot read '/Users/jane/Music/kick.wav'",
+            "files": [
+                { "path": "C:\\Users\\jane\\Music\\snare.wav" },
+                { "path": "/home/jane/samples/hat.wav" }
+            ],
+            "link": "https://example.com/Users/jane",
+            "count": 3
+        });
+
+        scrub_paths(&mut payload);
+
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "error": "Cannot read '<HOME>/Music/kick.wav'",
+                "files": [
+                    { "path": "<HOME>\\Music\\snare.wav" },
+                    { "path": "<HOME>/samples/hat.wav" }
+                ],
+                "link": "https://example.com/Users/jane",
+                "count": 3
+            })
+        );
+    }
+
+    #[test]
+    fn queue_event_works_after_a_panic_poisoned_the_lock() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+
+        std::thread::scope(|scope| {
+            let panicked = scope
+                .spawn(|| {
+                    let _queue = lock_queue();
+                    panic!("poisoning the queue lock");
+                })
+                .join();
+            assert!(panicked.is_err());
+        });
+        assert!(QUEUE_DB.is_poisoned());
+
+        queue_event(&event("after_panic"), 0).unwrap();
+        let pending = get_pending_events(DEFAULT_PENDING_LIMIT, 0).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.event_type, "after_panic");
+    }
+
+    #[test]
+    fn pages_cover_every_pending_event_once() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+        for _ in 0..250 {
+            queue_event(&event("page"), 0).unwrap();
+        }
+
+        let mut ids = Vec::new();
+        let mut offset = 0;
+        loop {
+            let page = get_pending_events(100, offset).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 100);
+            offset += page.len() as u32;
+            ids.extend(page.into_iter().map(|(id, _)| id));
+        }
+
+        let mut unique = ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(ids.len(), 250);
+        assert_eq!(unique.len(), 250);
+        // A zero limit still returns one event rather than none
+        assert_eq!(get_pending_events(0, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_large_batch_is_marked_sent_in_one_call() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+        let batch = IDS_PER_STATEMENT * 2 + 1;
+        for _ in 0..batch {
+            queue_event(&event("batched"), 0).unwrap();
+        }
+        let ids: Vec<i64> = {
+            let db = lock_queue();
+            let conn = db.as_ref().unwrap();
+            let mut stmt = conn.prepare("SELECT id FROM event_queue").unwrap();
+            let ids = stmt.query_map([], |row| row.get(0)).unwrap();
+            ids.map(Result::unwrap).collect()
+        };
+        assert_eq!(ids.len(), batch);
+
+        mark_sent(&ids).unwrap();
+
+        assert!(get_pending_events(DEFAULT_PENDING_LIMIT, 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn opting_out_survives_reopening_the_database() {
+        let _databases = test_support::use_test_databases();
+        let _reenable = Reenable;
+        set_enabled(false).unwrap();
+
+        // Reopen as on the next launch, starting from the default
+        *lock_queue() = None;
+        TELEMETRY_ENABLED.store(true, Ordering::Relaxed);
+        init_database().unwrap();
+
+        assert!(!TELEMETRY_ENABLED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn count_matches_pending_events_around_mark_sent_and_failures() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+
+        for _ in 0..3 {
+            queue_event(&event("count"), 0).unwrap();
+        }
+        let ids: Vec<i64> = get_pending_events(DEFAULT_PENDING_LIMIT, 0)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(count_pending_events().unwrap(), 3);
+
+        mark_sent(&ids[..1]).unwrap();
+        assert_eq!(count_pending_events().unwrap(), 2);
+
+        // An event that exhausted its attempts is no longer pending
+        for _ in 0..MAX_SEND_ATTEMPTS {
+            record_send_failure(&ids[1..2]).unwrap();
+        }
+        assert_eq!(count_pending_events().unwrap(), 1);
+        assert_eq!(
+            get_pending_events(DEFAULT_PENDING_LIMIT, 0).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn identical_events_in_the_window_are_counted_once() {
+        let _databases = test_support::use_test_databases();
+        empty_queue();
+
+        queue_event(&event("dedup"), 60).unwrap();
+        queue_event(&event("dedup"), 60).unwrap();
+
+        let pending = get_pending_events(DEFAULT_PENDING_LIMIT, 0).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.count, 2);
+
+        // A higher priority repeat is queued on its own so it isn't sent late
+        let urgent = QueuedEvent {
+            priority: 5,
+            ..event("dedup")
+        };
+        queue_event(&urgent, 60).unwrap();
+
+        let pending = get_pending_events(DEFAULT_PENDING_LIMIT, 0).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].1.priority, 5);
+        assert_eq!(pending[0].1.count, 1);
+    }
+}
@@ -0,0 +1,356 @@
+// Helpers shared by the unit tests
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+
+use crate::data_dir;
+
+static DATA_DIR: Once = Once::new();
+
+lazy_static::lazy_static! {
+    // Held by every test that uses the (process-wide) cache database
+    static ref DATABASES: Mutex<()> = Mutex::new(());
+    // Held by every test that changes a process-wide setting (e.g. the genre map)
+    static ref GLOBALS: Mutex<()> = Mutex::new(());
+}
+
+/// Folder holding everything this test run writes
+fn test_root() -> PathBuf {
+    std::env::temp_dir().join(format!("editors-workshop-tests-{}", std::process::id()))
+}
+
+/// A fresh, empty folder for one test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = test_root().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create test folder");
+    dir
+}
+
+/// Point the cache and telemetry databases at a scratch folder
+/// The databases are shared by the whole process, so the returned guard keeps other
+/// database tests out until the calling test finishes
+pub fn use_test_databases() -> MutexGuard<'static, ()> {
+    DATA_DIR.call_once(|| std::env::set_var(data_dir::DATA_DIR_ENV, temp_dir("data")));
+    DATABASES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keep other tests from changing process-wide settings until the calling test finishes
+/// Tests holding this must restore the defaults they change (e.g. from a drop guard)
+pub fn lock_globals() -> MutexGuard<'static, ()> {
+    GLOBALS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Write `contents` to `dir/name` and return the path as a string
+pub fn write_file(dir: &Path, name: &str, contents: &[u8]) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).expect("write test file");
+    path.to_string_lossy().to_string()
+}
+
+/// A Vorbis comment block (vendor string plus `KEY=value` pairs), as used by FLAC and Ogg
+fn vorbis_comments(comments: &[(&str, &str)]) -> Vec<u8> {
+    let vendor = b"editors-workshop tests";
+    let mut block = Vec::new();
+    block.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    block.extend_from_slice(vendor);
+    block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (key, value) in comments {
+        let comment = format!("{}={}", key, value);
+        block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        block.extend_from_slice(comment.as_bytes());
+    }
+    block
+}
+
+/// Write a silent 16-bit PCM WAV lasting `secs` seconds
+/// The samples are allocated with set_len, so even very long files are written instantly
+pub fn write_wav(path: &Path, sample_rate: u32, channels: u16, secs: u32) {
+    let data_len = sample_rate * u32::from(channels) * 2 * secs;
+    let header = wav_header(sample_rate, channels, data_len);
+
+    let mut file = File::create(path).expect("create wav");
+    file.write_all(&header).expect("write wav header");
+    file.set_len(header.len() as u64 + u64::from(data_len))
+        .expect("write wav samples");
+}
+
+/// Write a mono 16-bit PCM WAV holding the given samples
+pub fn write_wav_samples(path: &Path, sample_rate: u32, samples: &[i16]) {
+    let mut bytes = wav_header(sample_rate, 1, samples.len() as u32 * 2);
+    bytes.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+    std::fs::write(path, bytes).expect("write wav");
+}
+
+/// The 44-byte header of a 16-bit PCM WAV with `data_len` bytes of samples
+fn wav_header(sample_rate: u32, channels: u16, data_len: u32) -> Vec<u8> {
+    let block_align = channels * 2;
+    let byte_rate = sample_rate * u32::from(block_align);
+
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// Write a silent 16-bit big-endian PCM AIFF lasting `secs` seconds
+pub fn write_aiff(path: &Path, sample_rate: u32, channels: u16, secs: u32) {
+    let frames = sample_rate * secs;
+    let data_len = frames * u32::from(channels) * 2;
+
+    // Sample rate as an 80-bit extended float: biased exponent, then a normalized mantissa
+    let exponent = 31 - sample_rate.leading_zeros();
+    let mut rate = ((16_383 + exponent) as u16).to_be_bytes().to_vec();
+    rate.extend_from_slice(&(u64::from(sample_rate) << (63 - exponent)).to_be_bytes());
+
+    let mut file = Vec::new();
+    file.extend_from_slice(b"FORM");
+    file.extend_from_slice(&(4 + 26 + 16 + data_len).to_be_bytes());
+    file.extend_from_slice(b"AIFFCOMM");
+    file.extend_from_slice(&18u32.to_be_bytes());
+    file.extend_from_slice(&channels.to_be_bytes());
+    file.extend_from_slice(&frames.to_be_bytes());
+    file.extend_from_slice(&16u16.to_be_bytes()); // bits per sample
+    file.extend_from_slice(&rate);
+    file.extend_from_slice(b"SSND");
+    file.extend_from_slice(&(8 + data_len).to_be_bytes());
+    file.extend_from_slice(&[0; 8]); // offset, block size
+    file.resize(file.len() + data_len as usize, 0);
+    std::fs::write(path, file).expect("write aiff");
+}
+
+/// Write a one-second 16-bit stereo FLAC holding only metadata: STREAMINFO plus a Vorbis
+/// comment block with the given `KEY=value` pairs (no audio frames)
+pub fn write_flac(path: &Path, comments: &[(&str, &str)]) {
+    write_flac_blocks(path, comments, None);
+}
+
+/// Like `write_flac`, plus a front cover PICTURE block holding `image` (as image/png)
+pub fn write_flac_with_art(path: &Path, comments: &[(&str, &str)], image: &[u8]) {
+    let mut picture = 3u32.to_be_bytes().to_vec(); // front cover
+    for field in [&b"image/png"[..], b""] {
+        picture.extend_from_slice(&(field.len() as u32).to_be_bytes()); // MIME type, description
+        picture.extend_from_slice(field);
+    }
+    picture.extend_from_slice(&[0; 16]); // width, height, depth, colors (unknown)
+    picture.extend_from_slice(&(image.len() as u32).to_be_bytes());
+    picture.extend_from_slice(image);
+    write_flac_blocks(path, comments, Some(&picture));
+}
+
+/// Write a FLAC with STREAMINFO, a Vorbis comment block and optionally a PICTURE block
+fn write_flac_blocks(path: &Path, comments: &[(&str, &str)], picture: Option<&[u8]>) {
+    const SAMPLE_RATE: u64 = 44_100;
+
+    let mut stream_info = Vec::with_capacity(34);
+    stream_info.extend_from_slice(&4096u16.to_be_bytes()); // min block size
+    stream_info.extend_from_slice(&4096u16.to_be_bytes()); // max block size
+    stream_info.extend_from_slice(&[0; 6]); // min/max frame size (unknown)
+
+    // 20 bits sample rate, 3 bits channels - 1, 5 bits bits per sample - 1, 36 bits total samples
+    let packed = (SAMPLE_RATE << 44) | (1 << 41) | (15 << 36) | SAMPLE_RATE;
+    stream_info.extend_from_slice(&packed.to_be_bytes());
+    stream_info.extend_from_slice(&[0; 16]); // MD5 of the audio (unset)
+
+    let comment_block = vorbis_comments(comments);
+    let mut blocks = vec![(0u8, &stream_info[..]), (4u8, &comment_block[..])];
+    blocks.extend(picture.map(|picture| (6u8, picture)));
+
+    let mut file = Vec::new();
+    file.extend_from_slice(b"fLaC");
+    for (index, &(block_type, block)) in blocks.iter().enumerate() {
+        let last = index == blocks.len() - 1;
+        file.push(if last { 0x80 | block_type } else { block_type });
+        file.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+        file.extend_from_slice(block);
+    }
+    std::fs::write(path, file).expect("write flac");
+}
+
+/// Write a one-second stereo Ogg Vorbis file holding only its three header packets (the
+/// comment packet carries the given `KEY=value` pairs) and an empty final page
+pub fn write_ogg(path: &Path, comments: &[(&str, &str)]) {
+    const SAMPLE_RATE: u32 = 44_100;
+
+    let mut identification = b"\x01vorbis".to_vec();
+    identification.extend_from_slice(&0u32.to_le_bytes()); // Vorbis version
+    identification.push(2); // channels
+    identification.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    identification.extend_from_slice(&0u32.to_le_bytes()); // maximum bitrate
+    identification.extend_from_slice(&128_000u32.to_le_bytes()); // nominal bitrate
+    identification.extend_from_slice(&0u32.to_le_bytes()); // minimum bitrate
+    identification.push(0xb8); // block sizes 256 and 2048
+    identification.push(1); // framing bit
+
+    let mut comment = b"\x03vorbis".to_vec();
+    comment.extend_from_slice(&vorbis_comments(comments));
+    comment.push(1); // framing bit
+    let setup = b"\x05vorbis\x00".to_vec();
+
+    // BOS page with the identification header, a page with the other two headers, and an
+    // EOS page whose granule position (the sample count) gives the duration
+    let mut file = Vec::new();
+    file.extend(ogg_page(0x02, 0, 0, &[&identification]));
+    file.extend(ogg_page(0x00, 0, 1, &[&comment, &setup]));
+    file.extend(ogg_page(0x04, u64::from(SAMPLE_RATE), 2, &[]));
+    std::fs::write(path, file).expect("write ogg");
+}
+
+/// One Ogg page holding complete `packets`
+fn ogg_page(header_type: u8, granule_position: u64, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+    let mut segments = Vec::new();
+    for packet in packets {
+        segments.extend(vec![255u8; packet.len() / 255]);
+        segments.push((packet.len() % 255) as u8);
+    }
+
+    let mut page = b"OggS".to_vec();
+    page.push(0); // stream structure version
+    page.push(header_type);
+    page.extend_from_slice(&granule_position.to_le_bytes());
+    page.extend_from_slice(&1u32.to_le_bytes()); // stream serial number
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&0u32.to_le_bytes()); // checksum, filled in below
+    page.push(segments.len() as u8);
+    page.extend_from_slice(&segments);
+    for packet in packets {
+        page.extend_from_slice(packet);
+    }
+
+    let checksum = ogg_crc(&page);
+    page[22..26].copy_from_slice(&checksum.to_le_bytes());
+    page
+}
+
+/// The CRC-32 Ogg pages are checked with (polynomial 0x04c11db7, no reflection)
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |crc, &byte| {
+        (0..8).fold(crc ^ (u32::from(byte) << 24), |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Write a one-second stereo AAC `.m4a` holding only its boxes (no audio samples)
+/// `atoms` are text atoms like `(b"\xa9nam", "Title")`; `freeform` are iTunes `----` atoms
+/// like `("MOOD", "Happy")`
+pub fn write_m4a(path: &Path, atoms: &[(&[u8; 4], &str)], freeform: &[(&str, &str)]) {
+    const TIMESCALE: u32 = 44_100;
+
+    let mut file_type = b"M4A ".to_vec();
+    file_type.extend_from_slice(&0u32.to_be_bytes()); // minor version
+    file_type.extend_from_slice(b"M4A mp42isom");
+
+    // Movie header: times, timescale, duration, rate, volume, reserved, matrix, next track id
+    let mut movie_header = vec![0; 8];
+    movie_header.extend_from_slice(&TIMESCALE.to_be_bytes());
+    movie_header.extend_from_slice(&TIMESCALE.to_be_bytes());
+    movie_header.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    movie_header.extend_from_slice(&0x0100u16.to_be_bytes());
+    movie_header.extend_from_slice(&[0; 10]);
+    for value in [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        movie_header.extend_from_slice(&value.to_be_bytes());
+    }
+    movie_header.extend_from_slice(&[0; 24]);
+    movie_header.extend_from_slice(&2u32.to_be_bytes());
+
+    // Media header: times, timescale, duration, language
+    let mut media_header = vec![0; 8];
+    media_header.extend_from_slice(&TIMESCALE.to_be_bytes());
+    media_header.extend_from_slice(&TIMESCALE.to_be_bytes());
+    media_header.extend_from_slice(&[0x55, 0xc4, 0, 0]);
+
+    // One mp4a sample entry: reserved, data reference, version, revision, vendor, channels,
+    // sample size, compression id, packet size, sample rate (16.16)
+    let mut sample_entry = vec![0; 6];
+    sample_entry.extend_from_slice(&1u16.to_be_bytes());
+    sample_entry.extend_from_slice(&[0; 8]);
+    sample_entry.extend_from_slice(&2u16.to_be_bytes());
+    sample_entry.extend_from_slice(&16u16.to_be_bytes());
+    sample_entry.extend_from_slice(&[0; 4]);
+    sample_entry.extend_from_slice(&(TIMESCALE << 16).to_be_bytes());
+    let mut sample_descriptions = 1u32.to_be_bytes().to_vec();
+    sample_descriptions.extend(mp4_atom(b"mp4a", &sample_entry));
+
+    let track = mp4_atom(
+        b"trak",
+        &mp4_atom(
+            b"mdia",
+            &[
+                mp4_full_atom(b"mdhd", &media_header),
+                mp4_full_atom(b"hdlr", &mp4_handler(b"soun")),
+                mp4_atom(
+                    b"minf",
+                    &mp4_atom(b"stbl", &mp4_full_atom(b"stsd", &sample_descriptions)),
+                ),
+            ]
+            .concat(),
+        ),
+    );
+
+    let mut items = Vec::new();
+    for (name, value) in atoms {
+        items.extend(mp4_atom(name, &mp4_text_data(value)));
+    }
+    for (name, value) in freeform {
+        let mut atom = mp4_full_atom(b"mean", b"com.apple.iTunes");
+        atom.extend(mp4_full_atom(b"name", name.as_bytes()));
+        atom.extend(mp4_text_data(value));
+        items.extend(mp4_atom(b"----", &atom));
+    }
+    let mut meta = mp4_full_atom(b"hdlr", &mp4_handler(b"mdir"));
+    meta.extend(mp4_atom(b"ilst", &items));
+    let user_data = mp4_atom(b"udta", &mp4_full_atom(b"meta", &meta));
+
+    let mut file = mp4_atom(b"ftyp", &file_type);
+    file.extend(mp4_atom(
+        b"moov",
+        &[mp4_full_atom(b"mvhd", &movie_header), track, user_data].concat(),
+    ));
+    file.extend(mp4_atom(b"mdat", &[]));
+    std::fs::write(path, file).expect("write m4a");
+}
+
+/// An MP4 box: 32-bit size, name, contents
+fn mp4_atom(name: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let mut atom = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(name);
+    atom.extend_from_slice(contents);
+    atom
+}
+
+/// An MP4 full box (version 0, no flags)
+fn mp4_full_atom(name: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    mp4_atom(name, &[&[0; 4], contents].concat())
+}
+
+/// Handler reference contents for the given handler type (e.g. "soun")
+fn mp4_handler(handler_type: &[u8; 4]) -> Vec<u8> {
+    let mut handler = vec![0; 4];
+    handler.extend_from_slice(handler_type);
+    handler.extend_from_slice(&[0; 13]); // reserved, empty name
+    handler
+}
+
+/// A `data` atom holding UTF-8 text
+fn mp4_text_data(value: &str) -> Vec<u8> {
+    let mut data = 1u32.to_be_bytes().to_vec(); // version 0, type 1 (UTF-8)
+    data.extend_from_slice(&0u32.to_be_bytes()); // locale
+    data.extend_from_slice(value.as_bytes());
+    mp4_atom(b"data", &data)
+}
@@ -17,6 +17,19 @@ interface AudioMetadata {
   energy: string | null;
   bpm: number | null;
   duration_secs: number | null;
+  is_sfx: boolean;
+}
+
+interface PreviewEntry {
+  filename: string;
+  reason: "SfxPrefix" | "Override" | "GenreTag" | "MoodTag" | "OtherTag" | "FallbackUnknown" | "Flattened";
+  final_filename?: string;
+  skipped: boolean;
+}
+
+interface ScanReport {
+  files: AudioMetadata[];
+  failures: [string, string][];
 }
 
 interface OrganizeResult {
@@ -24,6 +37,7 @@ interface OrganizeResult {
   error_count: number;
   skipped_count: number;
   errors: string[];
+  moved: [string, string][]; // [source_path, destination_path]
 }
 
 interface DuplicateInfo {
@@ -31,6 +45,7 @@ interface DuplicateInfo {
   source_filename: string;
   existing_path: string;
   category: string;
+  match_type: "Name" | "Content" | "Fingerprint";
 }
 
 // Source duplicates: files with same name going to same folder (before organizing)
@@ -43,6 +58,7 @@ interface SourceDuplicateGroup {
   filename: string;
   category: string;
   files: SourceDuplicateFile[];
+  normalized_key?: string;
 }
 
 type FileStatus = "pending" | "scanning" | "scanned" | "organizing" | "done" | "error";
@@ -130,7 +146,7 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
   const [outputFolder, setOutputFolder] = useState<string | null>(null);
   const [organizeBy, setOrganizeBy] = useState<OrganizeBy>("genre");
   const [operation, setOperation] = useState<Operation>("copy");
-  const [preview, setPreview] = useState<Record<string, string[]> | null>(null);
+  const [preview, setPreview] = useState<Record<string, PreviewEntry[]> | null>(null);
   const [result, setResult] = useState<OrganizeResult | null>(null);
   const [dragOver, setDragOver] = useState(false);
   const [duplicates, setDuplicates] = useState<DuplicateInfo[]>([]);
@@ -258,8 +274,11 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
           for (const folder of folders) {
             setIsScanning(true);
             try {
-              const results: AudioMetadata[] = await invoke("scan_directory", { path: folder });
-              const newFiles: MusicFile[] = results.map((m) => ({
+              const report: ScanReport = await invoke("scan_directory", { path: folder });
+              if (report.failures.length > 0) {
+                console.warn(`${report.failures.length} file(s) couldn't be read:`, report.failures);
+              }
+              const newFiles: MusicFile[] = report.files.map((m) => ({
                 ...m,
                 status: "scanned" as FileStatus,
               }));
@@ -312,9 +331,10 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
   // Browse for files (uses Tauri dialog - provides full paths)
   const browseFiles = async () => {
     setShowBrowseMenu(false);
+    const extensions: string[] = await invoke("get_audio_extensions");
     const selected = await open({
       multiple: true,
-      filters: [{ name: "Audio", extensions: ["mp3", "wav", "MP3", "WAV"] }],
+      filters: [{ name: "Audio", extensions: [...extensions, ...extensions.map((ext) => ext.toUpperCase())] }],
       title: "Select audio files",
     });
 
@@ -336,8 +356,11 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
     if (selected && typeof selected === "string") {
       setIsScanning(true);
       try {
-        const results: AudioMetadata[] = await invoke("scan_directory", { path: selected });
-        const newFiles: MusicFile[] = results.map((m) => ({
+        const report: ScanReport = await invoke("scan_directory", { path: selected });
+        if (report.failures.length > 0) {
+          console.warn(`${report.failures.length} file(s) couldn't be read:`, report.failures);
+        }
+        const newFiles: MusicFile[] = report.files.map((m) => ({
           ...m,
           status: "scanned" as FileStatus,
         }));
@@ -438,9 +461,9 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
 
     try {
       const preparedFiles = prepareFilesForBackend(fileList, by);
-      const previewResult: Record<string, string[]> = await invoke("preview_organization", {
+      const previewResult: Record<string, PreviewEntry[]> = await invoke("preview_organization", {
         files: preparedFiles,
-        organizeBy: by,
+        options: { organize_by: by },
       });
       setPreview(previewResult);
     } catch (error) {
@@ -472,7 +495,7 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
     try {
       const groups: SourceDuplicateGroup[] = await invoke("find_source_duplicates", {
         files: preparedFiles,
-        organizeBy,
+        options: { organize_by: organizeBy },
       });
 
       if (groups.length > 0) {
@@ -574,7 +597,7 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
       const found: DuplicateInfo[] = await invoke("find_duplicates", {
         files: preparedFiles,
         outputFolder,
-        organizeBy,
+        options: { organize_by: organizeBy },
       });
 
       if (found.length > 0) {
@@ -657,8 +680,7 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
       const organizeResult: OrganizeResult = await invoke("organize_files", {
         files: preparedFiles,
         outputFolder,
-        organizeBy,
-        operation,
+        options: { organize_by: organizeBy, operation },
       });
 
       setResult(organizeResult);
@@ -684,6 +706,7 @@ const TheSmelter = ({ isActive = true, onUpdateCount }: TheSmelterProps) => {
         error_count: filesToOrganize.length,
         skipped_count: 0,
         errors: [String(error)],
+        moved: [],
       });
     }
 
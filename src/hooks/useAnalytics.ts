@@ -5,6 +5,9 @@ import posthog from "posthog-js";
 interface QueuedEvent {
   event_type: string;
   payload: Record<string, unknown>;
+  attempts: number;
+  priority: number; // higher is sent first
+  count: number; // identical events collapsed into this one (see dedup_window_secs)
 }
 
 // Sync pending events when online
@@ -22,7 +25,11 @@ async function syncPendingEvents() {
         }
         sentIds.push(id);
       } catch {
-        // Stop on first failure (likely offline)
+        // Stop on first failure (likely offline). Only this event's attempt is counted, and
+        // since pending events come highest priority and oldest first, one event that keeps
+        // failing is retried first on every sync and holds back the rest of the queue until
+        // it reaches the backend's retry cap (MAX_SEND_ATTEMPTS) and stops being returned
+        await invoke("record_telemetry_failure", { ids: [id] });
         break;
       }
     }
@@ -129,6 +136,9 @@ export function useAnalytics() {
 // Utility to toggle analytics
 export function setAnalyticsEnabled(enabled: boolean) {
   localStorage.setItem("analytics_enabled", String(enabled));
+  invoke("set_telemetry_enabled", { enabled }).catch((e) =>
+    console.error("Failed to update telemetry setting:", e)
+  );
   if (!enabled) {
     posthog.opt_out_capturing();
   } else {